@@ -0,0 +1,216 @@
+use amp_sdk::{
+    AmpOptions, AssistantContent, ContinueThread, Permission, StreamMessage, UserContent, execute,
+};
+use anyhow::Result;
+use futures::StreamExt;
+use futures::stream::{self, LocalBoxStream};
+use serde_json::json;
+use std::path::{Component, Path, PathBuf};
+
+use super::{AgentBackend, AgentEvent, AgentRequest};
+use crate::permissions::{EDIT_TOOLS, PermissionAction, PermissionPolicy};
+
+/// Runs prompts through the Amp CLI via amp-sdk.
+pub struct AmpBackend;
+
+impl AgentBackend for AmpBackend {
+    fn name(&self) -> String {
+        "amp".to_string()
+    }
+
+    fn enforces_permissions(&self) -> bool {
+        true
+    }
+
+    fn run<'a>(&'a self, request: AgentRequest<'a>) -> LocalBoxStream<'a, Result<AgentEvent>> {
+        let unrestricted = request.permissions.is_unrestricted();
+        let options = AmpOptions::builder()
+            .cwd(request.cwd.to_string_lossy().to_string())
+            .dangerously_allow_all(unrestricted)
+            .maybe_permissions(
+                (!unrestricted).then(|| amp_permissions(request.permissions, request.cwd)),
+            )
+            .maybe_continue_thread(request.continue_thread.map(ContinueThread::ThreadId))
+            .build();
+
+        execute(request.prompt, Some(options))
+            .flat_map(|message| {
+                let events = match message {
+                    Ok(message) => events(message).into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e.into())],
+                };
+                stream::iter(events)
+            })
+            .boxed_local()
+    }
+}
+
+/// Translates a policy into Amp permission rules. Amp applies the first rule that matches,
+/// so the most specific rules come first.
+fn amp_permissions(policy: &PermissionPolicy, cwd: &Path) -> Vec<Permission> {
+    let rule = |tool: &str, action: amp_sdk::PermissionAction| Permission::new(tool, action);
+    let allow = amp_sdk::PermissionAction::Allow;
+    let reject = amp_sdk::PermissionAction::Reject;
+    let mut rules = Vec::new();
+
+    for tool in policy.deny_tools.iter().flatten() {
+        rules.push(rule(tool, reject));
+    }
+    if let Some(commands) = &policy.deny_commands {
+        rules.push(Permission {
+            matches: Some(json!({ "cmd": commands })),
+            ..rule("Bash", reject)
+        });
+    }
+    if let Some(commands) = &policy.allow_commands {
+        rules.push(Permission {
+            matches: Some(json!({ "cmd": commands })),
+            ..rule("Bash", allow)
+        });
+        rules.push(rule("Bash", reject));
+    }
+    if let Some(paths) = &policy.paths {
+        // The agent passes absolute paths to its edit tools
+        let paths: Vec<String> = paths
+            .iter()
+            .map(|p| {
+                let path: PathBuf = cwd
+                    .join(p)
+                    .components()
+                    .filter(|c| !matches!(c, Component::CurDir))
+                    .collect();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        for tool in EDIT_TOOLS {
+            rules.push(Permission {
+                matches: Some(json!({ "path": paths })),
+                ..rule(tool, allow)
+            });
+            rules.push(rule(tool, reject));
+        }
+    }
+    for tool in policy.allow_tools.iter().flatten() {
+        rules.push(rule(tool, allow));
+    }
+    rules.push(match policy.default_action() {
+        PermissionAction::Allow => rule("*", allow),
+        PermissionAction::Reject => rule("*", reject),
+    });
+
+    rules
+}
+
+/// The raw message followed by the events translated from it.
+fn events(message: StreamMessage) -> Vec<AgentEvent> {
+    let raw = serde_json::to_value(&message).map(|message| AgentEvent::Raw { message });
+    let translated = match message {
+        StreamMessage::System(msg) => vec![AgentEvent::Session { id: msg.session_id }],
+        StreamMessage::Assistant(msg) => msg
+            .message
+            .content
+            .into_iter()
+            .map(|content| match content {
+                AssistantContent::Text(text) => AgentEvent::Text { text: text.text },
+                AssistantContent::ToolUse(tool) => AgentEvent::ToolUse {
+                    id: tool.id,
+                    name: tool.name,
+                    input: tool.input,
+                },
+            })
+            .collect(),
+        StreamMessage::User(msg) => msg
+            .message
+            .content
+            .into_iter()
+            .filter_map(|content| match content {
+                UserContent::ToolResult(result) => Some(AgentEvent::ToolResult {
+                    tool_use_id: result.tool_use_id,
+                    content: result.content,
+                    is_error: result.is_error,
+                }),
+                UserContent::Text(_) => None,
+            })
+            .collect(),
+        StreamMessage::Result(msg) => vec![AgentEvent::Result {
+            is_error: msg.is_error,
+            error: msg.error,
+            duration_ms: msg.duration_ms,
+            num_turns: msg.num_turns,
+        }],
+    };
+    raw.into_iter().chain(translated).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(policy: &PermissionPolicy) -> serde_json::Value {
+        serde_json::to_value(amp_permissions(policy, Path::new("/work"))).unwrap()
+    }
+
+    fn strings(items: &[&str]) -> Option<Vec<String>> {
+        Some(items.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn unrestricted_policy_allows_everything() {
+        assert_eq!(
+            rules(&PermissionPolicy::default()),
+            json!([{"tool": "*", "action": "allow"}])
+        );
+    }
+
+    #[test]
+    fn specific_rules_come_before_broad_ones() {
+        let policy = PermissionPolicy {
+            allow_tools: strings(&["Read"]),
+            deny_tools: strings(&["web_search"]),
+            allow_commands: strings(&["cargo *"]),
+            deny_commands: strings(&["cargo publish*"]),
+            paths: strings(&["src/**", "./tests"]),
+            default: None,
+        };
+        let paths = json!({"path": ["/work/src/**", "/work/tests"]});
+
+        assert_eq!(
+            rules(&policy),
+            json!([
+                {"tool": "web_search", "action": "reject"},
+                // Denied commands win over an allow pattern that also matches them
+                {"tool": "Bash", "action": "reject", "matches": {"cmd": ["cargo publish*"]}},
+                {"tool": "Bash", "action": "allow", "matches": {"cmd": ["cargo *"]}},
+                {"tool": "Bash", "action": "reject"},
+                {"tool": "edit_file", "action": "allow", "matches": paths},
+                {"tool": "edit_file", "action": "reject"},
+                {"tool": "create_file", "action": "allow", "matches": paths},
+                {"tool": "create_file", "action": "reject"},
+                {"tool": "undo_edit", "action": "allow", "matches": paths},
+                {"tool": "undo_edit", "action": "reject"},
+                {"tool": "Read", "action": "allow"},
+                {"tool": "*", "action": "reject"},
+            ])
+        );
+    }
+
+    #[test]
+    fn read_only_policy_allows_only_looking_around() {
+        let rules = rules(&PermissionPolicy::read_only());
+        let rules = rules.as_array().unwrap();
+        let (last, allowed) = rules.split_last().unwrap();
+
+        assert_eq!(last, &json!({"tool": "*", "action": "reject"}));
+        assert!(allowed.iter().all(|rule| rule["action"] == "allow"));
+        let tools: Vec<&str> = allowed
+            .iter()
+            .map(|rule| rule["tool"].as_str().unwrap())
+            .collect();
+        assert!(tools.contains(&"Read") && tools.contains(&"Grep"));
+        assert!(
+            !tools
+                .iter()
+                .any(|tool| EDIT_TOOLS.contains(tool) || *tool == "Bash")
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use futures::stream::{self, LocalBoxStream};
+use std::process::Stdio;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+use super::{AgentBackend, AgentEvent, AgentRequest};
+
+/// Placeholder in a command argument that is replaced by the prompt.
+const PROMPT_PLACEHOLDER: &str = "{prompt}";
+
+/// Lines of stderr kept for the error message when the command fails.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Runs any agent CLI that takes a prompt and writes its answer to stdout.
+///
+/// The prompt replaces `{prompt}` in the arguments, or is written to stdin if no argument
+/// contains it. Every stdout line is reported as assistant text; a non-zero exit status
+/// is reported as an error result carrying the end of stderr.
+pub struct CliBackend {
+    command: Vec<String>,
+}
+
+impl CliBackend {
+    pub fn new(command: Vec<String>) -> Result<Self> {
+        anyhow::ensure!(
+            command
+                .first()
+                .is_some_and(|program| !program.trim().is_empty()),
+            "agent.command must name a program to run"
+        );
+        Ok(Self { command })
+    }
+}
+
+impl AgentBackend for CliBackend {
+    fn name(&self) -> String {
+        self.command[0].clone()
+    }
+
+    fn run<'a>(&'a self, request: AgentRequest<'a>) -> LocalBoxStream<'a, Result<AgentEvent>> {
+        let prompt_in_args = self.command.iter().any(|a| a.contains(PROMPT_PLACEHOLDER));
+        let mut cmd = Command::new(&self.command[0]);
+        cmd.args(
+            self.command[1..]
+                .iter()
+                .map(|a| a.replace(PROMPT_PLACEHOLDER, request.prompt)),
+        )
+        .current_dir(request.cwd)
+        .stdin(if prompt_in_args {
+            Stdio::null()
+        } else {
+            Stdio::piped()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+        let mut child = match cmd
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.command[0]))
+        {
+            Ok(child) => child,
+            Err(e) => return stream::once(async { Err(e) }).boxed_local(),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            let prompt = request.prompt.to_string();
+            tokio::spawn(async move {
+                let _ = stdin.write_all(prompt.as_bytes()).await;
+            });
+        }
+        // Read stderr concurrently so a chatty agent cannot fill the pipe and stall
+        let stderr = child.stderr.take().map(|mut stderr| {
+            tokio::spawn(async move {
+                let mut out = String::new();
+                let _ = stderr.read_to_string(&mut out).await;
+                out
+            })
+        });
+        let Some(stdout) = child.stdout.take() else {
+            return stream::once(async { Err(anyhow::anyhow!("Agent stdout was not captured")) })
+                .boxed_local();
+        };
+
+        let started = Instant::now();
+        let lines = BufReader::new(stdout).lines();
+        stream::unfold(Some((lines, child, stderr)), move |state| async move {
+            let (mut lines, mut child, stderr) = state?;
+            if let Ok(Some(line)) = lines.next_line().await {
+                let event = AgentEvent::Text {
+                    text: format!("{line}\n"),
+                };
+                return Some((Ok(event), Some((lines, child, stderr))));
+            }
+
+            let status = match child.wait().await {
+                Ok(status) => status,
+                Err(e) => return Some((Err(e.into()), None)),
+            };
+            let stderr = match stderr {
+                Some(task) => task.await.unwrap_or_default(),
+                None => String::new(),
+            };
+            let error = (!status.success()).then(|| {
+                let lines: Vec<&str> = stderr.trim().lines().collect();
+                let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+                format!("exited with {status}\n{tail}").trim().to_string()
+            });
+            let event = AgentEvent::Result {
+                is_error: error.is_some(),
+                error,
+                duration_ms: started.elapsed().as_millis() as u64,
+                num_turns: 1,
+            };
+            Some((Ok(event), None))
+        })
+        .boxed_local()
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use futures::stream::{self, LocalBoxStream};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::process::Command;
+
+use super::{AgentBackend, AgentEvent, AgentRequest};
+use crate::types::{StoryStatus, load_prd, save_prd};
+
+/// A scripted stand-in for a real agent, for exercising the run loop offline.
+///
+/// Each request is answered by the first response in the script that matches it and has
+/// uses left. A response can run shell commands in the working directory (to edit files
+/// and commit) and mark the story as passing, just like an agent would.
+pub struct FakeBackend {
+    script: FakeScript,
+    /// How many times each response has been used
+    used: Mutex<Vec<u32>>,
+    /// Requests answered so far, used to make up session IDs
+    requests: AtomicUsize,
+}
+
+#[derive(Debug, Deserialize)]
+struct FakeScript {
+    /// PRD file the responses mark stories in, relative to the working directory
+    #[serde(default = "default_prd")]
+    prd: PathBuf,
+    responses: Vec<FakeResponse>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FakeResponse {
+    /// Only answer prompts for this story
+    story: Option<String>,
+    /// Only answer prompts containing this text
+    prompt_contains: Option<String>,
+    /// How many requests this response answers (unlimited if unset)
+    times: Option<u32>,
+    /// Assistant text to send back
+    text: String,
+    /// Shell commands run in the working directory before answering
+    commands: Vec<String>,
+    /// Set the status of the story named in the prompt to `passed`
+    mark_passed: bool,
+    /// Set the status of the story named in the prompt to this instead
+    set_status: Option<StoryStatus>,
+    /// Finish with an error result carrying this message
+    error: Option<String>,
+    /// Time to wait before answering, in milliseconds
+    delay_ms: u64,
+    /// Session ID to report instead of a made-up one (a continued session keeps its ID)
+    session: Option<String>,
+}
+
+fn default_prd() -> PathBuf {
+    PathBuf::from("prd.json")
+}
+
+impl FakeBackend {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fake agent script: {}", path.display()))?;
+        let script: FakeScript = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse fake agent script: {}", path.display()))?;
+        let used = Mutex::new(vec![0; script.responses.len()]);
+        Ok(Self {
+            script,
+            used,
+            requests: AtomicUsize::new(0),
+        })
+    }
+
+    /// Picks the response for `prompt` and counts it as used.
+    fn respond(&self, prompt: &str, story_id: Option<&str>) -> Option<&FakeResponse> {
+        let mut used = self.used.lock().unwrap();
+        let index = self
+            .script
+            .responses
+            .iter()
+            .enumerate()
+            .position(|(i, r)| {
+                r.times.is_none_or(|times| used[i] < times)
+                    && r.story
+                        .as_deref()
+                        .is_none_or(|story| story_id == Some(story))
+                    && r.prompt_contains
+                        .as_deref()
+                        .is_none_or(|text| prompt.contains(text))
+            })?;
+        used[index] += 1;
+        Some(&self.script.responses[index])
+    }
+}
+
+/// The story an iteration prompt is about, read from its `**Story ID:**` line.
+fn prompt_story_id(prompt: &str) -> Option<&str> {
+    prompt
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("**Story ID:**"))
+        .map(str::trim)
+}
+
+impl AgentBackend for FakeBackend {
+    fn name(&self) -> String {
+        "fake".to_string()
+    }
+
+    fn run<'a>(&'a self, request: AgentRequest<'a>) -> LocalBoxStream<'a, Result<AgentEvent>> {
+        let story_id = prompt_story_id(request.prompt);
+        let response = self.respond(request.prompt, story_id);
+        let request_number = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let session = AgentEvent::Session {
+            id: request
+                .continue_thread
+                .clone()
+                .or_else(|| response.and_then(|r| r.session.clone()))
+                .unwrap_or_else(|| format!("fake-{request_number}")),
+        };
+
+        let work = async move {
+            let Some(response) = response else {
+                return vec![Ok(AgentEvent::Result {
+                    is_error: true,
+                    error: Some("no scripted response matches this prompt".to_string()),
+                    duration_ms: 0,
+                    num_turns: 0,
+                })];
+            };
+
+            tokio::time::sleep(Duration::from_millis(response.delay_ms)).await;
+            let mut events = vec![Ok(AgentEvent::Text {
+                text: response.text.clone(),
+            })];
+
+            for command in &response.commands {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .current_dir(request.cwd)
+                    .kill_on_drop(true)
+                    .status()
+                    .await;
+                match status {
+                    Ok(status) if status.success() => {}
+                    Ok(status) => {
+                        events.push(Err(anyhow::anyhow!("`{command}` {status}")));
+                    }
+                    Err(e) => events.push(Err(e.into())),
+                }
+            }
+
+            let status = response
+                .set_status
+                .or(response.mark_passed.then_some(StoryStatus::Passed));
+            if let (Some(status), Some(id)) = (status, story_id) {
+                let prd_path = request.cwd.join(&self.script.prd);
+                let marked = load_prd(&prd_path).and_then(|mut prd| {
+                    if let Some(story) = prd.stories.iter_mut().find(|s| s.id == id) {
+                        story.status = status;
+                    }
+                    save_prd(&prd_path, &prd)
+                });
+                if let Err(e) = marked {
+                    events.push(Err(e));
+                }
+            }
+
+            events.push(Ok(AgentEvent::Result {
+                is_error: response.error.is_some(),
+                error: response.error.clone(),
+                duration_ms: response.delay_ms,
+                num_turns: 1,
+            }));
+            events
+        };
+
+        stream::once(async { Ok(session) })
+            .chain(stream::once(work).flat_map(stream::iter))
+            .boxed_local()
+    }
+}
@@ -0,0 +1,277 @@
+//! Coding agents ramph can drive. Each backend turns a prompt into a stream of
+//! [`AgentEvent`]s; [`run_iteration`] renders that stream the same way for all of them.
+
+use anyhow::Result;
+use futures::StreamExt;
+use futures::stream::LocalBoxStream;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::config::AgentConfig;
+use crate::output;
+use crate::permissions::PermissionPolicy;
+use crate::transcript::TranscriptWriter;
+
+mod amp;
+mod cli;
+mod fake;
+
+pub use amp::AmpBackend;
+pub use cli::CliBackend;
+pub use fake::FakeBackend;
+
+/// A single prompt for an agent to work on.
+pub struct AgentRequest<'a> {
+    pub prompt: &'a str,
+    /// Directory the agent works in (canonicalized)
+    pub cwd: &'a Path,
+    /// Session to continue instead of starting a new one, if the backend supports it
+    pub continue_thread: Option<String>,
+    /// Tools the agent may use, if the backend can enforce it
+    pub permissions: &'a PermissionPolicy,
+}
+
+/// Something the agent did or said while working on a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// A message sent to the agent; backends never emit this, it only appears in transcripts
+    Prompt {
+        text: String,
+    },
+    /// The agent's session ID, reported once at the start
+    Session {
+        id: String,
+    },
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    },
+    /// Final event of a request
+    Result {
+        is_error: bool,
+        error: Option<String>,
+        duration_ms: u64,
+        num_turns: u32,
+    },
+    /// A message exactly as the backend sent it, ahead of the events translated from it,
+    /// so transcripts keep what the translation leaves out
+    Raw {
+        message: serde_json::Value,
+    },
+}
+
+pub trait AgentBackend {
+    /// Short name shown in run headers, e.g. `amp`.
+    fn name(&self) -> String;
+
+    /// Whether the backend applies [`AgentRequest::permissions`]; those that don't run
+    /// the agent with whatever access it grants itself.
+    fn enforces_permissions(&self) -> bool {
+        false
+    }
+
+    /// Starts working on `request`. Dropping the stream abandons the request.
+    fn run<'a>(&'a self, request: AgentRequest<'a>) -> LocalBoxStream<'a, Result<AgentEvent>>;
+}
+
+/// Builds the backend selected by the `agent` section of the config.
+pub fn from_config(config: &AgentConfig, cwd: &Path) -> Result<Box<dyn AgentBackend>> {
+    Ok(match config {
+        AgentConfig::Amp => Box::new(AmpBackend),
+        AgentConfig::Cli { command } => Box::new(CliBackend::new(command.clone())?),
+        AgentConfig::Fake { script } => Box::new(FakeBackend::load(&cwd.join(script))?),
+    })
+}
+
+#[derive(Default)]
+pub struct IterationOptions<'a> {
+    pub spinner: Option<&'a ProgressBar>,
+    /// Echo assistant text to stdout as it arrives
+    pub stream_text: bool,
+    /// Agent session to continue instead of starting a new one
+    pub continue_thread: Option<String>,
+    /// File to append the prompt and every event to
+    pub transcript: Option<&'a Path>,
+    /// Tools the agent may use (unrestricted by default)
+    pub permissions: PermissionPolicy,
+}
+
+/// What an agent reported about its session, filled in as events arrive.
+#[derive(Debug, Default, Clone)]
+pub struct SessionInfo {
+    pub id: Option<String>,
+    pub num_turns: Option<u32>,
+}
+
+/// Shows an event the way a live iteration does: tool use on the spinner, text on stdout
+/// (if `stream_text`), the rest in verbose output.
+pub fn render_event(event: &AgentEvent, spinner: Option<&ProgressBar>, stream_text: bool) {
+    match event {
+        AgentEvent::Prompt { text } => {
+            output::verbose(&format!("prompt: {} chars", text.len()));
+        }
+        AgentEvent::Session { id } => output::verbose(&format!("session: {id}")),
+        AgentEvent::Text { text } => {
+            if stream_text && !output::is_quiet() {
+                print!("{text}");
+            }
+        }
+        AgentEvent::ToolUse { name, .. } => {
+            if let Some(s) = spinner {
+                s.set_message(format!("Using tool: {name}..."));
+            }
+            output::verbose(&format!("using tool: {name}"));
+        }
+        AgentEvent::ToolResult { .. } => {}
+        AgentEvent::Result {
+            duration_ms,
+            num_turns,
+            ..
+        } => {
+            output::verbose(&format!("done: {duration_ms}ms, {num_turns} turns"));
+        }
+        AgentEvent::Raw { .. } => {}
+    }
+}
+
+/// Runs `prompt` through `backend` and returns the assistant's text.
+///
+/// `session` is filled in as soon as the agent reports it, so the session ID is available
+/// even if the returned future is dropped mid-stream.
+pub async fn run_iteration(
+    backend: &dyn AgentBackend,
+    prompt: &str,
+    cwd: &Path,
+    opts: IterationOptions<'_>,
+    session: &mut SessionInfo,
+) -> Result<String> {
+    let cwd = cwd.canonicalize()?;
+    let IterationOptions {
+        spinner,
+        stream_text,
+        continue_thread,
+        transcript,
+        permissions,
+    } = opts;
+
+    let mut transcript = transcript.and_then(|path| {
+        TranscriptWriter::open(path)
+            .inspect_err(|e| output::warn(&format!("Not saving transcript: {e:#}")))
+            .ok()
+    });
+    let mut record = |event: &AgentEvent| {
+        if let Some(writer) = &mut transcript
+            && let Err(e) = writer.record(event)
+        {
+            output::warn(&format!("Not saving transcript: {e:#}"));
+            transcript = None;
+        }
+    };
+    record(&AgentEvent::Prompt {
+        text: prompt.to_string(),
+    });
+
+    let request = AgentRequest {
+        prompt,
+        cwd: &cwd,
+        continue_thread,
+        permissions: &permissions,
+    };
+    let mut stream = backend.run(request);
+    let mut output_text = String::new();
+
+    while let Some(result) = stream.next().await {
+        let event = match result {
+            Ok(event) => event,
+            Err(e) => {
+                output::error(&format!("stream error: {}", e));
+                continue;
+            }
+        };
+        record(&event);
+        render_event(&event, spinner, stream_text);
+
+        match event {
+            AgentEvent::Session { id } => session.id = Some(id),
+            AgentEvent::Text { text } => output_text.push_str(&text),
+            AgentEvent::Result {
+                is_error,
+                error,
+                num_turns,
+                ..
+            } => {
+                session.num_turns = Some(num_turns);
+                if is_error {
+                    anyhow::bail!("{} error: {}", backend.name(), error.unwrap_or_default());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(output_text)
+}
+
+/// Sends SIGTERM to the agent process working in `workdir`. Some backends (amp-sdk among
+/// them) do not kill their process when the stream is dropped, so a cancelled iteration
+/// would otherwise keep editing the tree.
+#[cfg(unix)]
+pub fn terminate_agent(workdir: &Path) {
+    use std::process::Command;
+
+    let Ok(children) = Command::new("pgrep")
+        .args(["-P", &std::process::id().to_string()])
+        .output()
+    else {
+        return;
+    };
+    let workdir = workdir
+        .canonicalize()
+        .unwrap_or_else(|_| workdir.to_path_buf());
+
+    for pid in String::from_utf8_lossy(&children.stdout).split_whitespace() {
+        // A child whose directory can't be read is left alone: under --jobs it may be
+        // another story's agent or verification command.
+        match process_cwd(pid) {
+            Some(cwd) if cwd == workdir => {
+                output::verbose(&format!("terminating agent process {pid}"));
+                let _ = Command::new("kill").args(["-TERM", pid]).status();
+            }
+            Some(_) => {}
+            None => output::verbose(&format!(
+                "not terminating process {pid}: cannot tell which directory it runs in"
+            )),
+        }
+    }
+}
+
+/// Working directory of process `pid`, from /proc where available and `lsof` elsewhere
+/// (e.g. macOS).
+#[cfg(unix)]
+fn process_cwd(pid: &str) -> Option<std::path::PathBuf> {
+    if let Ok(cwd) = std::fs::read_link(format!("/proc/{pid}/cwd")) {
+        return Some(cwd);
+    }
+    let output = std::process::Command::new("lsof")
+        .args(["-a", "-p", pid, "-d", "cwd", "-Fn"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix('n'))
+        .map(std::path::PathBuf::from)
+}
+
+#[cfg(not(unix))]
+pub fn terminate_agent(_workdir: &Path) {}
@@ -0,0 +1,342 @@
+//! An overview of an existing project for the planner: its file tree, manifests, READMEs
+//! and recent history, so proposed stories fit the code that is already there.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::config::RAMPH_DIR;
+use crate::git;
+
+/// Files whose contents are always included when they exist at the top of the project.
+const KEY_FILES: &[&str] = &[
+    "README.md",
+    "README",
+    "AGENTS.md",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "go.mod",
+];
+
+/// Directories left out of the file tree when the project is not a git repository.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "dist", "build", "__pycache__"];
+
+/// The `plan_context` section of the config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanContextConfig {
+    /// Directory levels shown in the file tree; deeper files are counted per directory
+    pub depth: usize,
+    /// Largest excerpt of any single file, in bytes
+    pub max_file_bytes: usize,
+    /// Size budget for the whole snapshot, in bytes
+    pub max_bytes: usize,
+    /// Number of recent commits to include (0 leaves the log out)
+    pub git_log: usize,
+    /// Globs of further files to include in full, e.g. `docs/architecture.md`
+    pub include: Vec<String>,
+    /// Globs of files and directories to leave out entirely, e.g. `vendor`
+    pub exclude: Vec<String>,
+}
+
+impl Default for PlanContextConfig {
+    fn default() -> Self {
+        Self {
+            depth: 3,
+            max_file_bytes: 8_000,
+            max_bytes: 40_000,
+            git_log: 15,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl PlanContextConfig {
+    pub fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            self.max_bytes > 0 && self.max_file_bytes > 0,
+            "plan_context size limits must be greater than zero"
+        );
+        for (name, globs) in [("include", &self.include), ("exclude", &self.exclude)] {
+            anyhow::ensure!(
+                globs.iter().all(|g| !g.trim().is_empty()),
+                "plan_context.{name} contains an empty pattern"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A rendered project overview.
+pub struct CodebaseSnapshot {
+    /// Markdown sections ready to paste into a prompt
+    pub markdown: String,
+    /// Files in the project (after exclusions)
+    pub file_count: usize,
+    /// Files whose contents were included
+    pub included: Vec<String>,
+    /// Files left out because the size budget ran out
+    pub omitted: Vec<String>,
+}
+
+/// Collects the snapshot of the project at `cwd`. Returns `None` for an empty directory.
+pub fn collect_snapshot(
+    cwd: &Path,
+    config: &PlanContextConfig,
+) -> Result<Option<CodebaseSnapshot>> {
+    let excluded = |path: &str| {
+        path.starts_with(&format!("{RAMPH_DIR}/"))
+            || config.exclude.iter().any(|g| glob_matches(g, path))
+    };
+    let mut files: Vec<String> = match git::list_files(cwd) {
+        Ok(files) => files
+            .into_iter()
+            .filter(|f| cwd.join(f).is_file())
+            .collect(),
+        Err(_) => walk_files(cwd, Path::new("")),
+    };
+    files.retain(|f| !excluded(f));
+    files.sort();
+    files.dedup();
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut budget = config.max_bytes;
+    let mut markdown = String::new();
+    let mut push_section = |markdown: &mut String, section: String| -> bool {
+        if section.len() > budget {
+            return false;
+        }
+        budget -= section.len();
+        markdown.push_str(&section);
+        true
+    };
+
+    let tree = render_tree(&files, config.depth.max(1));
+    let tree = truncate(&tree, config.max_file_bytes);
+    push_section(
+        &mut markdown,
+        format!("### File tree\n\n```text\n{tree}\n```\n\n"),
+    );
+
+    let mut wanted: Vec<&String> = KEY_FILES
+        .iter()
+        .filter_map(|key| files.iter().find(|f| f == key))
+        .collect();
+    wanted.extend(
+        files
+            .iter()
+            .filter(|f| config.include.iter().any(|g| glob_matches(g, f)))
+            .filter(|f| !KEY_FILES.contains(&f.as_str())),
+    );
+
+    let mut included = Vec::new();
+    let mut omitted = Vec::new();
+    for file in wanted {
+        let Ok(content) = fs::read_to_string(cwd.join(file)) else {
+            continue;
+        };
+        let section = format!(
+            "### {file}\n\n```\n{}\n```\n\n",
+            truncate(content.trim_end(), config.max_file_bytes)
+        );
+        if push_section(&mut markdown, section) {
+            included.push(file.clone());
+        } else {
+            omitted.push(file.clone());
+        }
+    }
+
+    if config.git_log > 0
+        && let Ok(log) = git::recent_log(cwd, config.git_log)
+        && !log.is_empty()
+    {
+        push_section(
+            &mut markdown,
+            format!("### Recent commits\n\n```text\n{log}\n```\n\n"),
+        );
+    }
+
+    if !omitted.is_empty() {
+        markdown.push_str(&format!(
+            "(Left out to stay within the size limit: {})\n",
+            omitted.join(", ")
+        ));
+    }
+
+    Ok(Some(CodebaseSnapshot {
+        markdown: markdown.trim_end().to_string(),
+        file_count: files.len(),
+        included,
+        omitted,
+    }))
+}
+
+/// Files under `dir` (relative to `root`) for projects outside git, skipping hidden and
+/// build output directories.
+fn walk_files(root: &Path, dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(root.join(dir)) else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = dir.join(&name);
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                files.extend(walk_files(root, &path));
+            }
+        } else if file_type.is_file() {
+            files.push(path.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    files
+}
+
+/// Indented tree of `files` down to `depth` levels; deeper files are summarized as a
+/// count on the last directory shown.
+fn render_tree(files: &[String], depth: usize) -> String {
+    // Path components of each entry, mapped to the number of files hidden below it
+    let mut entries: BTreeMap<Vec<&str>, usize> = BTreeMap::new();
+    let mut dirs: HashSet<Vec<&str>> = HashSet::new();
+    for file in files {
+        let parts: Vec<&str> = file.split('/').collect();
+        let shown = parts.len().min(depth);
+        for end in 1..parts.len().min(depth + 1) {
+            dirs.insert(parts[..end].to_vec());
+        }
+        for end in 1..shown {
+            entries.entry(parts[..end].to_vec()).or_default();
+        }
+        if parts.len() > depth {
+            *entries.entry(parts[..shown].to_vec()).or_default() += 1;
+        } else {
+            entries.entry(parts).or_default();
+        }
+    }
+
+    entries
+        .iter()
+        .map(|(path, hidden)| {
+            let indent = "  ".repeat(path.len() - 1);
+            let name = path[path.len() - 1];
+            match (dirs.contains(path), hidden) {
+                (true, 0) => format!("{indent}{name}/"),
+                (true, 1) => format!("{indent}{name}/ (1 file not shown)"),
+                (true, n) => format!("{indent}{name}/ ({n} files not shown)"),
+                (false, _) => format!("{indent}{name}"),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `text` cut to at most `max` bytes on a line boundary where possible.
+fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = text[..end].rfind('\n').unwrap_or(end);
+    format!("{}\n... (truncated)", &text[..cut])
+}
+
+/// Matches a path against a glob. `*` and `?` stay within one path segment, `**` spans
+/// any number of them. A pattern without `/` matches the file name or any directory on
+/// the path, so `target` or `*.lock` apply anywhere.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern
+        .trim()
+        .trim_start_matches("./")
+        .trim_end_matches('/');
+    if pattern.contains('/') {
+        return match_segments(pattern.as_bytes(), path.as_bytes())
+            || match_segments(format!("{pattern}/**").as_bytes(), path.as_bytes());
+    }
+    path.split('/')
+        .any(|segment| match_segments(pattern.as_bytes(), segment.as_bytes()))
+}
+
+fn match_segments(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            match_segments(rest, path)
+                || path
+                    .iter()
+                    .enumerate()
+                    .any(|(i, c)| *c == b'/' && match_segments(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != b'/')
+            .any(|i| match_segments(rest, &path[i..])),
+        [b'?', rest @ ..] => {
+            matches!(path.first(), Some(c) if *c != b'/') && match_segments(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && match_segments(rest, &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_slash_matches_any_segment() {
+        assert!(glob_matches("target", "target/debug/ramph"));
+        assert!(glob_matches("target", "crates/x/target/out"));
+        assert!(glob_matches("*.lock", "Cargo.lock"));
+        assert!(glob_matches("*.lock", "web/yarn.lock"));
+        assert!(!glob_matches("*.lock", "Cargo.toml"));
+    }
+
+    #[test]
+    fn single_star_stays_within_a_segment() {
+        assert!(glob_matches("src/*.rs", "src/main.rs"));
+        assert!(!glob_matches("src/*.rs", "src/backend/mod.rs"));
+        assert!(glob_matches("tests/auth_?.rs", "tests/auth_1.rs"));
+        assert!(!glob_matches("tests/auth_?.rs", "tests/auth_12.rs"));
+    }
+
+    #[test]
+    fn double_star_spans_segments() {
+        assert!(glob_matches("src/**", "src/main.rs"));
+        assert!(glob_matches("src/**", "src/backend/fake.rs"));
+        assert!(glob_matches("src/**/*.rs", "src/main.rs"));
+        assert!(glob_matches("src/**/*.rs", "src/backend/fake.rs"));
+        assert!(!glob_matches("src/**", "tests/main.rs"));
+    }
+
+    #[test]
+    fn directory_pattern_matches_its_contents() {
+        assert!(glob_matches("docs/api", "docs/api/index.md"));
+        assert!(glob_matches("./docs/api/", "docs/api/index.md"));
+        assert!(!glob_matches("docs/api", "docs/apiary.md"));
+    }
+
+    #[test]
+    fn render_tree_summarizes_deep_files() {
+        let files = [
+            "Cargo.toml".to_string(),
+            "src/main.rs".to_string(),
+            "src/backend/amp.rs".to_string(),
+            "src/backend/fake.rs".to_string(),
+        ];
+        assert_eq!(
+            render_tree(&files, 2),
+            "Cargo.toml\nsrc/\n  backend/ (2 files not shown)\n  main.rs"
+        );
+    }
+}
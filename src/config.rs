@@ -0,0 +1,286 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::codebase::PlanContextConfig;
+use crate::permissions::PermissionPolicy;
+use crate::types::VerifyStep;
+
+pub const DEFAULT_CONFIG_FILE: &str = "ramph.json";
+pub const RAMPH_DIR: &str = ".ramph";
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Project-level settings, read from `ramph.json` next to the PRD.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Commands ramph runs after every iteration; all must exit 0 for a story to pass
+    #[serde(default)]
+    pub verify: Vec<VerifyStep>,
+    /// Attempts a story gets before it is blocked, unless the story sets its own
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    /// Wall-clock limit for a single iteration, e.g. "30m"
+    #[serde(default)]
+    pub iteration_timeout: Option<String>,
+    /// What to do with the working tree when an attempt fails
+    #[serde(default)]
+    pub on_failure: Option<FailurePolicy>,
+    /// What to do when an attempt changes files outside its story's `scope`
+    #[serde(default)]
+    pub scope_policy: Option<ScopePolicy>,
+    /// Which coding agent runs the iterations
+    #[serde(default)]
+    pub agent: AgentConfig,
+    /// Tools the agent may use during runs; unrestricted if unset
+    #[serde(default)]
+    pub permissions: PermissionPolicy,
+    /// Tools the planning and extraction sessions may use; read-only if unset
+    #[serde(default)]
+    pub plan_permissions: Option<PermissionPolicy>,
+    /// What ramph tells the planner about the existing codebase
+    #[serde(default)]
+    pub plan_context: PlanContextConfig,
+}
+
+/// The coding agent ramph drives, e.g. `{"backend": "cli", "command": ["my-agent", "{prompt}"]}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum AgentConfig {
+    /// Amp, through amp-sdk
+    #[default]
+    Amp,
+    /// Any CLI that prints its answer to stdout; `{prompt}` in an argument is replaced by
+    /// the prompt, otherwise the prompt is written to stdin
+    Cli { command: Vec<String> },
+    /// Scripted responses read from a JSON file, for testing without an agent
+    Fake { script: PathBuf },
+}
+
+/// How to clean up after a failed attempt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Leave the agent's changes in place for the next attempt
+    #[default]
+    Keep,
+    /// Stash uncommitted changes under a message naming the story and attempt
+    Stash,
+    /// Hard reset to the commit the attempt started from
+    Reset,
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailurePolicy::Keep => "keep",
+            FailurePolicy::Stash => "stash",
+            FailurePolicy::Reset => "reset",
+        })
+    }
+}
+
+/// How to handle an attempt that changed files outside its story's `scope`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ScopePolicy {
+    /// Report the files in the output and progress log
+    #[default]
+    Warn,
+    /// Count the attempt as failed
+    Fail,
+    /// Put the files back as they were before the attempt and carry on
+    Revert,
+}
+
+impl fmt::Display for ScopePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScopePolicy::Warn => "warn",
+            ScopePolicy::Fail => "fail",
+            ScopePolicy::Revert => "revert",
+        })
+    }
+}
+
+pub fn load_config(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let config: Config = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+
+    for step in &config.verify {
+        anyhow::ensure!(
+            !step.command.trim().is_empty(),
+            "Verification step '{}' has an empty command",
+            step.name
+        );
+    }
+
+    anyhow::ensure!(
+        config.max_attempts != Some(0),
+        "max_attempts must be at least 1"
+    );
+
+    if let Some(timeout) = &config.iteration_timeout {
+        parse_duration(timeout).context("Invalid iteration_timeout in config")?;
+    }
+
+    config
+        .permissions
+        .validate()
+        .context("Invalid permissions in config")?;
+    if let Some(policy) = &config.plan_permissions {
+        policy
+            .validate()
+            .context("Invalid plan_permissions in config")?;
+    }
+    config
+        .plan_context
+        .validate()
+        .context("Invalid plan_context in config")?;
+
+    Ok(config)
+}
+
+/// Returns the `.ramph` working directory under `cwd`, creating it (and a `.gitignore`
+/// that hides it from git) on first use.
+pub fn ramph_dir(cwd: &Path) -> Result<PathBuf> {
+    let dir = cwd.join(RAMPH_DIR);
+    if !dir.exists() {
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        fs::write(dir.join(".gitignore"), "*\n").context("Failed to write .ramph/.gitignore")?;
+    }
+    Ok(dir)
+}
+
+/// Longest duration `parse_duration` accepts (100 years), so deadlines computed from it
+/// cannot overflow.
+const MAX_DURATION_SECS: u64 = 100 * 365 * 24 * 3600;
+
+/// Parses durations like `90`, `45s`, `30m`, `2h` or `1h30m` (a bare number is seconds).
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let too_long = || anyhow::anyhow!("Invalid duration '{input}': too long");
+    if let Ok(secs) = input.parse::<u64>() {
+        anyhow::ensure!(secs > 0, "Duration must be greater than zero");
+        anyhow::ensure!(secs <= MAX_DURATION_SECS, too_long());
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => anyhow::bail!("Invalid duration '{input}': unknown unit '{c}' (use h, m or s)"),
+        };
+        let value: u64 = digits
+            .parse()
+            .with_context(|| format!("Invalid duration '{input}'"))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .filter(|total| *total <= MAX_DURATION_SECS)
+            .ok_or_else(too_long)?;
+        digits.clear();
+    }
+
+    anyhow::ensure!(
+        digits.is_empty(),
+        "Invalid duration '{input}': missing unit after {digits}"
+    );
+    anyhow::ensure!(total > 0, "Duration must be greater than zero");
+    Ok(Duration::from_secs(total))
+}
+
+/// Formats a duration the way `parse_duration` reads it, e.g. `1h30m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    let mut out = String::new();
+    if h > 0 {
+        out.push_str(&format!("{h}h"));
+    }
+    if m > 0 {
+        out.push_str(&format!("{m}m"));
+    }
+    if s > 0 || out.is_empty() {
+        out.push_str(&format!("{s}s"));
+    }
+    out
+}
+
+/// Parses a run deadline: either a duration from now (`8h`) or a local time of day
+/// (`06:30`), which means the next time the clock shows it.
+pub fn parse_deadline(input: &str) -> Result<DateTime<Local>> {
+    let now = Local::now();
+    if let Ok(time) = NaiveTime::parse_from_str(input.trim(), "%H:%M") {
+        let mut date = now.date_naive();
+        if time <= now.time() {
+            date = date.succ_opt().context("Deadline is out of range")?;
+        }
+        return date
+            .and_time(time)
+            .and_local_timezone(Local)
+            .earliest()
+            .with_context(|| format!("Deadline {input} does not exist in the local timezone"));
+    }
+
+    let duration = parse_duration(input).with_context(|| {
+        format!("Invalid deadline '{input}' (use a duration like 8h or a time like 06:30)")
+    })?;
+    Ok(now + chrono::Duration::from_std(duration)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration(" 2h ").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for input in ["", "0", "0m", "10x", "5m3", "h", "1.5h"] {
+            assert!(parse_duration(input).is_err(), "{input:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration("99999999999999999h").is_err());
+        assert!(parse_duration("18446744073709551615").is_err());
+        assert!(parse_duration("18446744073709551615h1s").is_err());
+    }
+
+    #[test]
+    fn format_duration_round_trips() {
+        for input in ["45s", "30m", "2h", "1h30m"] {
+            let duration = parse_duration(input).unwrap();
+            assert_eq!(
+                parse_duration(&format_duration(duration)).unwrap(),
+                duration
+            );
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::ramph_dir;
+
+const PLANS_DIR: &str = "plans";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Speaker {
+    User,
+    Agent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub speaker: Speaker,
+    pub text: String,
+}
+
+/// The exchange between the user and the planning agent, in order.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub turns: Vec<Turn>,
+}
+
+impl Conversation {
+    pub fn push(&mut self, speaker: Speaker, text: &str) {
+        self.turns.push(Turn {
+            speaker,
+            text: text.trim().to_string(),
+        });
+    }
+
+    /// Reads a conversation saved by `save`. Any other file is taken as a single user
+    /// message, so notes or a chat exported elsewhere can be extracted too.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read conversation file: {}", path.display()))?;
+        if let Ok(conversation) = serde_json::from_str::<Conversation>(&content) {
+            return Ok(conversation);
+        }
+        anyhow::ensure!(
+            !content.trim().is_empty(),
+            "Conversation file {} is empty",
+            path.display()
+        );
+        let mut conversation = Conversation::default();
+        conversation.push(Speaker::User, &content);
+        Ok(conversation)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write conversation file: {}", path.display()))
+    }
+
+    /// Plain-text transcript for prompts, e.g. `User: ...` and `Assistant: ...` paragraphs.
+    pub fn render(&self) -> String {
+        self.turns
+            .iter()
+            .map(|turn| {
+                let label = match turn.speaker {
+                    Speaker::User => "User",
+                    Speaker::Agent => "Assistant",
+                };
+                format!("{label}: {}", turn.text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// A new file under `.ramph/plans/` to keep a planning conversation in.
+pub fn new_conversation_path(cwd: &Path) -> Result<PathBuf> {
+    let name = Local::now().format("%Y%m%d-%H%M%S");
+    Ok(ramph_dir(cwd)?.join(PLANS_DIR).join(format!("{name}.json")))
+}
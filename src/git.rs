@@ -0,0 +1,344 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchCheckout {
+    Created,
+    Switched,
+    AlreadyOn,
+}
+
+fn git(cwd: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(cwd)
+        .output()
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+pub fn ensure_repo(cwd: &Path) -> Result<()> {
+    git(cwd, &["rev-parse", "--is-inside-work-tree"])
+        .with_context(|| format!("{} is not inside a git repository", cwd.display()))?;
+    Ok(())
+}
+
+pub fn head_commit(cwd: &Path) -> Result<String> {
+    git(cwd, &["rev-parse", "HEAD"]).context("Failed to resolve HEAD (does the repo have commits?)")
+}
+
+pub fn current_branch(cwd: &Path) -> Result<String> {
+    git(cwd, &["rev-parse", "--abbrev-ref", "HEAD"])
+}
+
+pub fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+/// Returns true if tracked files other than `exclude` (relative to `cwd`) have uncommitted
+/// changes. Untracked files are ignored.
+pub fn is_dirty(cwd: &Path, exclude: &[&str]) -> Result<bool> {
+    let excludes: Vec<String> = exclude.iter().map(|p| format!(":(exclude){p}")).collect();
+    let mut args = vec!["status", "--porcelain", "--untracked-files=no", "--", "."];
+    args.extend(excludes.iter().map(String::as_str));
+    let status = git(cwd, &args)?;
+    Ok(!status.is_empty())
+}
+
+/// Tracked files with uncommitted changes plus untracked, non-ignored files, relative to `cwd`.
+pub fn dirty_files(cwd: &Path) -> Result<Vec<String>> {
+    let tracked = git(cwd, &["diff", "--name-only", "--relative", "HEAD"])?;
+    let untracked = git(cwd, &["ls-files", "--others", "--exclude-standard"])?;
+    Ok(tracked
+        .lines()
+        .chain(untracked.lines())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Tracked and untracked, non-ignored files under `cwd`, relative to it.
+pub fn list_files(cwd: &Path) -> Result<Vec<String>> {
+    let files = git(
+        cwd,
+        &["ls-files", "--cached", "--others", "--exclude-standard"],
+    )?;
+    Ok(files.lines().map(str::to_string).collect())
+}
+
+/// The last `count` commits, one line each.
+pub fn recent_log(cwd: &Path, count: usize) -> Result<String> {
+    git(
+        cwd,
+        &[
+            "log",
+            "--oneline",
+            "--no-decorate",
+            "-n",
+            &count.to_string(),
+        ],
+    )
+}
+
+/// Stashes uncommitted changes to `paths` (untracked files included). Paths git knows
+/// nothing about, such as an untracked file that has since been deleted, are skipped.
+/// Returns false if there was nothing to stash.
+pub fn stash_push(cwd: &Path, message: &str, paths: &[String]) -> Result<bool> {
+    if paths.is_empty() {
+        return Ok(false);
+    }
+    let mut args = vec!["ls-files", "--"];
+    args.extend(paths.iter().map(String::as_str));
+    let tracked = git(cwd, &args)?;
+    let tracked: Vec<&str> = tracked.lines().collect();
+    let paths: Vec<&str> = paths
+        .iter()
+        .map(String::as_str)
+        .filter(|path| tracked.contains(path) || cwd.join(path).exists())
+        .collect();
+    if paths.is_empty() {
+        return Ok(false);
+    }
+    let mut args = vec!["stash", "push", "--include-untracked", "-m", message, "--"];
+    args.extend(paths);
+
+    let before = git(cwd, &["stash", "list"])?.lines().count();
+    git(cwd, &args)?;
+    let after = git(cwd, &["stash", "list"])?.lines().count();
+    Ok(after > before)
+}
+
+pub fn reset_hard(cwd: &Path, commit: &str) -> Result<()> {
+    git(cwd, &["reset", "-q", "--hard", commit])?;
+    Ok(())
+}
+
+pub fn branch_exists(cwd: &Path, name: &str) -> Result<bool> {
+    let status = Command::new("git")
+        .args([
+            "show-ref",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{name}"),
+        ])
+        .current_dir(cwd)
+        .status()
+        .context("Failed to run git")?;
+    Ok(status.success())
+}
+
+pub fn checkout_branch(cwd: &Path, name: &str, base: Option<&str>) -> Result<BranchCheckout> {
+    if current_branch(cwd)? == name {
+        return Ok(BranchCheckout::AlreadyOn);
+    }
+
+    if branch_exists(cwd, name)? {
+        git(cwd, &["checkout", name])?;
+        return Ok(BranchCheckout::Switched);
+    }
+
+    match base {
+        Some(base) => git(cwd, &["checkout", "-b", name, base])?,
+        None => git(cwd, &["checkout", "-b", name])?,
+    };
+    Ok(BranchCheckout::Created)
+}
+
+pub fn commit_count_since(cwd: &Path, since: &str) -> Result<usize> {
+    let count = git(cwd, &["rev-list", "--count", &format!("{since}..HEAD")])?;
+    count.parse().context("Failed to parse commit count")
+}
+
+/// Path of `cwd` relative to the repository root (empty at the root, otherwise ends with `/`).
+pub fn show_prefix(cwd: &Path) -> Result<String> {
+    git(cwd, &["rev-parse", "--show-prefix"])
+}
+
+pub fn worktree_add(cwd: &Path, path: &Path, branch: &str, start: &str) -> Result<()> {
+    let path = path.to_string_lossy();
+    git(cwd, &["worktree", "add", "-b", branch, &path, start])?;
+    Ok(())
+}
+
+pub fn worktree_remove(cwd: &Path, path: &Path) -> Result<()> {
+    let path = path.to_string_lossy();
+    git(cwd, &["worktree", "remove", "--force", &path])?;
+    Ok(())
+}
+
+pub fn delete_branch(cwd: &Path, name: &str) -> Result<()> {
+    git(cwd, &["branch", "-D", name])?;
+    Ok(())
+}
+
+/// Paths (relative to `cwd`) among `paths` whose content differs between `from` and HEAD.
+pub fn changed_paths(cwd: &Path, from: &str, paths: &[&str]) -> Result<Vec<String>> {
+    let mut args = vec!["diff", "--name-only", "--relative", from, "HEAD", "--"];
+    args.extend_from_slice(paths);
+    let out = git(cwd, &args)?;
+    Ok(out.lines().map(str::to_string).collect())
+}
+
+/// Resets `paths` (relative to `cwd`) to their content at `commit` and commits the result.
+/// Paths that did not exist at `commit` are untracked, and with `delete_new` also deleted
+/// from the working tree. Other staged changes stay staged and out of the commit. Returns
+/// true if a commit was made.
+pub fn restore_paths(
+    cwd: &Path,
+    commit: &str,
+    paths: &[String],
+    message: &str,
+    delete_new: bool,
+) -> Result<bool> {
+    for path in paths {
+        if exists_at(cwd, commit, path)? {
+            git(cwd, &["checkout", commit, "--", path])?;
+        } else {
+            git(
+                cwd,
+                &["rm", "-q", "--cached", "--ignore-unmatch", "--", path],
+            )?;
+            if delete_new {
+                let _ = std::fs::remove_file(cwd.join(path));
+            }
+        }
+    }
+
+    // Commit from a scratch index holding HEAD plus the restored paths
+    let index = cwd.join(git(
+        cwd,
+        &["rev-parse", "--git-path", "ramph-restore-index"],
+    )?);
+    let committed = commit_restored(cwd, &index, commit, paths, message);
+    let _ = std::fs::remove_file(&index);
+    committed
+}
+
+fn exists_at(cwd: &Path, commit: &str, path: &str) -> Result<bool> {
+    Ok(Command::new("git")
+        .args(["cat-file", "-e", &format!("{commit}:./{path}")])
+        .current_dir(cwd)
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run git")?
+        .success())
+}
+
+/// Commits `paths` as they are at `commit` on top of HEAD, staging them in `index` rather
+/// than the repository's index. Returns false if that changes nothing.
+fn commit_restored(
+    cwd: &Path,
+    index: &Path,
+    commit: &str,
+    paths: &[String],
+    message: &str,
+) -> Result<bool> {
+    let with_index = |args: &[&str]| {
+        let mut command = Command::new("git");
+        command
+            .args(args)
+            .current_dir(cwd)
+            .env("GIT_INDEX_FILE", index);
+        command
+    };
+    let run = |args: &[&str]| -> Result<()> {
+        let output = with_index(args).output().context("Failed to run git")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        Ok(())
+    };
+
+    run(&["read-tree", "HEAD"])?;
+    let mut reset = vec!["reset", "-q", commit, "--"];
+    reset.extend(paths.iter().map(String::as_str));
+    run(&reset)?;
+
+    let staged = !with_index(&["diff", "--cached", "--quiet"])
+        .status()
+        .context("Failed to run git")?
+        .success();
+    if staged {
+        run(&["commit", "-q", "-m", message])?;
+    }
+    Ok(staged)
+}
+
+#[derive(Debug)]
+pub enum MergeResult {
+    Merged,
+    Conflict { files: Vec<String>, message: String },
+}
+
+/// Merges `branch` into the current branch with a merge commit, aborting on conflict.
+pub fn merge_branch(cwd: &Path, branch: &str, message: &str) -> Result<MergeResult> {
+    let output = Command::new("git")
+        .args(["merge", "--no-ff", "-m", message, branch])
+        .current_dir(cwd)
+        .output()
+        .context("Failed to run git")?;
+
+    if output.status.success() {
+        return Ok(MergeResult::Merged);
+    }
+
+    let conflicted = git(cwd, &["diff", "--name-only", "--diff-filter=U"]).unwrap_or_default();
+    let files = conflicted.lines().map(str::to_string).collect();
+    let _ = git(cwd, &["merge", "--abort"]);
+
+    let mut message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        message.push('\n');
+        message.push_str(stderr.trim());
+    }
+
+    Ok(MergeResult::Conflict { files, message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::GitRepo;
+
+    #[test]
+    fn restore_paths_commits_only_the_restored_paths() {
+        let repo = GitRepo::new();
+        repo.write_file("scoped.txt", "base\n");
+        repo.write_file("outside.txt", "base\n");
+        repo.git(&["add", "-A"]);
+        repo.git(&["commit", "-qm", "base"]);
+        let base = repo.git(&["rev-parse", "HEAD"]);
+
+        repo.write_file("outside.txt", "changed\n");
+        repo.git(&["commit", "-qam", "work"]);
+        repo.write_file("scoped.txt", "staged\n");
+        repo.write_file("new.txt", "new\n");
+        repo.git(&["add", "scoped.txt", "new.txt"]);
+
+        let paths = ["outside.txt".to_string(), "new.txt".to_string()];
+        assert!(restore_paths(&repo.dir, &base, &paths, "revert", true).unwrap());
+
+        assert_eq!(
+            repo.git(&["show", "--name-only", "--format=", "HEAD"]),
+            "outside.txt"
+        );
+        assert_eq!(repo.read_file("outside.txt"), "base\n");
+        assert!(!repo.dir.join("new.txt").exists());
+        // The in-scope work is still staged, not committed
+        assert_eq!(repo.git(&["diff", "--cached", "--name-only"]), "scoped.txt");
+        assert_eq!(repo.read_file("scoped.txt"), "staged\n");
+    }
+}
@@ -1,12 +1,27 @@
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::Duration;
 
-mod amp;
+mod backend;
+mod codebase;
+mod config;
+mod conversation;
+mod git;
 mod output;
+mod permissions;
 mod prompts;
+mod signal;
+mod snapshot;
+mod state;
+#[cfg(test)]
+mod testutil;
+mod transcript;
 mod types;
+mod verify;
 mod workflows;
+mod worktree;
 
 #[derive(Parser)]
 #[command(name = "ramph", about = "Ralph workflow runner using Amp")]
@@ -30,9 +45,17 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Execute stories from an existing PRD
-    Run(RunArgs),
+    Run(Box<RunArgs>),
     /// Interactively create a new PRD with AI assistance
     Plan(PlanArgs),
+    /// Print the saved agent transcripts of a story
+    Logs(TranscriptArgs),
+    /// Re-render a story's saved transcripts as a live run would show them
+    Replay(TranscriptArgs),
+    /// Add, edit, remove, reorder or reset stories in a PRD
+    Story(StoryArgs),
+    /// Show where a PRD stands: story summary, what runs next, what is stuck and the last run
+    Status(StatusArgs),
 }
 
 #[derive(Args)]
@@ -51,8 +74,88 @@ struct RunArgs {
     #[arg(long)]
     prompt: Option<PathBuf>,
 
+    /// Path to the project config file (verification commands, defaults)
+    #[arg(long, default_value = config::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+
     #[arg(long, default_value_t = 25)]
     max_iterations: usize,
+
+    /// Default attempts per story before it is blocked [default: 3, or `max_attempts` in config]
+    #[arg(long)]
+    max_attempts: Option<u32>,
+
+    /// Run up to N independent stories at once, each in its own git worktree
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Base ref to create the PRD branch from (defaults to the current HEAD)
+    #[arg(long)]
+    base: Option<String>,
+
+    /// Start even if the working tree has uncommitted changes
+    #[arg(long, default_value_t = false)]
+    allow_dirty: bool,
+
+    /// Continue the last interrupted run from .ramph/state.json
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Wall-clock limit per iteration, e.g. 30m or 1h30m
+    #[arg(long, value_parser = config::parse_duration)]
+    iteration_timeout: Option<Duration>,
+
+    /// Start no new stories after this point: a duration (8h) or a local time (06:30)
+    #[arg(long, value_parser = config::parse_deadline)]
+    deadline: Option<DateTime<Local>>,
+
+    /// What to do with a failed attempt's changes [default: keep, or `on_failure` in config]
+    #[arg(long, value_enum)]
+    on_failure: Option<config::FailurePolicy>,
+
+    /// What to do when a story changes files outside its `scope` [default: warn, or `scope_policy` in config]
+    #[arg(long, value_enum)]
+    scope_policy: Option<config::ScopePolicy>,
+
+    /// Render every prompt in scheduling order without calling the agent or touching git
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// With --dry-run, write each prompt to a file in this directory instead of stdout
+    #[arg(long, requires = "dry_run")]
+    prompts_dir: Option<PathBuf>,
+
+    /// Only run these stories (repeatable or comma-separated)
+    #[arg(long = "story", value_name = "ID", value_delimiter = ',')]
+    stories: Vec<String>,
+
+    /// Skip stories scheduled before this one
+    #[arg(long, value_name = "ID")]
+    from: Option<String>,
+
+    /// Only run stories with one of these tags (repeatable or comma-separated)
+    #[arg(long = "tag", value_name = "TAG", value_delimiter = ',')]
+    tags: Vec<String>,
+
+    /// Never run these stories (repeatable or comma-separated)
+    #[arg(long, value_name = "ID", value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Never run stories with any of these tags (repeatable or comma-separated)
+    #[arg(long = "exclude-tag", value_name = "TAG", value_delimiter = ',')]
+    exclude_tags: Vec<String>,
+
+    /// Re-run selected stories even if they already pass or are blocked
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Run even if the agent backend cannot enforce the configured tool permissions
+    #[arg(long, default_value_t = false)]
+    allow_unenforced_permissions: bool,
+
+    /// End with the PRD summary grouped by tag
+    #[arg(long, default_value_t = false)]
+    group_by_tag: bool,
 }
 
 #[derive(Args)]
@@ -63,6 +166,10 @@ struct PlanArgs {
     #[arg(short, long, default_value = "prd.json")]
     output: PathBuf,
 
+    /// Path to the project config file (agent backend)
+    #[arg(long, default_value = config::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+
     /// Initial project description (optional, can be provided interactively)
     #[arg(short, long)]
     description: Option<String>,
@@ -70,6 +177,199 @@ struct PlanArgs {
     /// Overwrite existing PRD file if it exists
     #[arg(long, default_value_t = false)]
     force: bool,
+
+    /// Skip the conversation and extract the PRD from a saved conversation (or any text file)
+    #[arg(long, conflicts_with = "description")]
+    extract_from: Option<PathBuf>,
+
+    /// How many times the agent may fix a PRD that fails to parse or validate
+    #[arg(long, default_value_t = 3)]
+    repair_attempts: u32,
+
+    /// Plan additional stories for the existing PRD instead of writing a new one
+    #[arg(long, conflicts_with = "force")]
+    amend: bool,
+
+    /// Don't give the agent a snapshot of the existing codebase
+    #[arg(long, default_value_t = false)]
+    no_context: bool,
+
+    /// Plan even if the agent backend cannot enforce `plan_permissions`
+    #[arg(long, default_value_t = false)]
+    allow_unenforced_permissions: bool,
+
+    /// Show the proposed PRD grouped by tag
+    #[arg(long, default_value_t = false)]
+    group_by_tag: bool,
+}
+
+#[derive(Args)]
+struct TranscriptArgs {
+    #[arg(short, long, default_value = ".")]
+    cwd: PathBuf,
+
+    /// Story whose transcripts to show
+    story: String,
+
+    /// Run to read from (defaults to the latest run that attempted the story)
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Show only this attempt
+    #[arg(long)]
+    attempt: Option<u32>,
+}
+
+#[derive(Args)]
+struct StatusArgs {
+    #[arg(short, long, default_value = ".")]
+    cwd: PathBuf,
+
+    #[arg(long, default_value = "prd.json")]
+    prd: PathBuf,
+
+    /// Print the status as JSON on stdout
+    #[arg(long, default_value_t = false)]
+    json: bool,
+
+    /// Group the story summary by tag
+    #[arg(long, default_value_t = false, conflicts_with = "json")]
+    group_by_tag: bool,
+}
+
+#[derive(Args)]
+struct StoryArgs {
+    #[arg(short, long, default_value = ".", global = true)]
+    cwd: PathBuf,
+
+    #[arg(long, default_value = "prd.json", global = true)]
+    prd: PathBuf,
+
+    #[command(subcommand)]
+    command: StoryCommand,
+}
+
+#[derive(Subcommand)]
+enum StoryCommand {
+    /// Append a story, numbered after the highest existing ID
+    Add {
+        /// Title of the new story
+        title: String,
+
+        /// ID to use instead of the next free one
+        #[arg(long)]
+        id: Option<String>,
+
+        #[command(flatten)]
+        fields: StoryFieldArgs,
+    },
+    /// Change fields of a story
+    Edit {
+        /// Story to edit
+        id: String,
+
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Set the status directly, e.g. `skipped`
+        #[arg(long, value_enum)]
+        status: Option<types::StoryStatus>,
+
+        /// Remove all dependencies
+        #[arg(long, conflicts_with = "depends_on")]
+        clear_depends_on: bool,
+
+        /// Remove all tags
+        #[arg(long, conflicts_with = "tag")]
+        clear_tags: bool,
+
+        /// Remove the scope, so the story may change any file
+        #[arg(long, conflicts_with = "scope")]
+        clear_scope: bool,
+
+        #[command(flatten)]
+        fields: StoryFieldArgs,
+    },
+    /// Delete stories
+    Remove {
+        #[arg(required = true)]
+        ids: Vec<String>,
+
+        /// Also drop the removed stories from other stories' dependencies
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Renumber priorities 1, 2, 3... in scheduling order
+    Reorder {
+        /// Stories to move to the front, in this order
+        ids: Vec<String>,
+    },
+    /// Put stories back to pending and clear their attempt count
+    Reset {
+        /// Stories to reset
+        ids: Vec<String>,
+
+        /// Reset stories with this tag (repeatable)
+        #[arg(long)]
+        tag: Vec<String>,
+
+        /// Reset every story
+        #[arg(long, conflicts_with_all = ["ids", "tag"])]
+        all: bool,
+    },
+}
+
+/// Story fields shared by `story add` and `story edit`. Repeated flags build a list that
+/// replaces the story's current one.
+#[derive(Args)]
+struct StoryFieldArgs {
+    #[arg(short, long)]
+    description: Option<String>,
+
+    /// Lower numbers run first [default for new stories: after the last one]
+    #[arg(long)]
+    priority: Option<i32>,
+
+    /// Acceptance criterion (repeatable)
+    #[arg(long)]
+    criterion: Vec<String>,
+
+    /// ID of a story that must pass first (repeatable)
+    #[arg(long)]
+    depends_on: Vec<String>,
+
+    /// Tag (repeatable)
+    #[arg(long)]
+    tag: Vec<String>,
+
+    /// Glob of files the story may change (repeatable)
+    #[arg(long)]
+    scope: Vec<String>,
+
+    /// Attempts before the story is blocked
+    #[arg(long)]
+    max_attempts: Option<u32>,
+
+    /// Wall-clock limit per attempt, e.g. 45m
+    #[arg(long)]
+    timeout: Option<String>,
+}
+
+impl StoryFieldArgs {
+    fn into_edit(self, title: Option<String>) -> types::StoryEdit {
+        types::StoryEdit {
+            title,
+            description: self.description,
+            priority: self.priority,
+            acceptance_criteria: self.criterion,
+            depends_on: self.depends_on,
+            tags: self.tag,
+            scope: self.scope,
+            max_attempts: self.max_attempts,
+            timeout: self.timeout,
+            ..Default::default()
+        }
+    }
 }
 
 #[tokio::main]
@@ -87,17 +387,100 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Commands::Run(args) => {
-            workflows::run_command(
-                args.cwd,
-                args.prd,
-                args.progress,
-                args.prompt,
-                args.max_iterations,
-            )
+            workflows::run_command(workflows::RunOptions {
+                cwd: args.cwd,
+                prd: args.prd,
+                progress: args.progress,
+                prompt: args.prompt,
+                config: args.config,
+                max_iterations: args.max_iterations,
+                max_attempts: args.max_attempts,
+                jobs: args.jobs,
+                base: args.base,
+                allow_dirty: args.allow_dirty,
+                resume: args.resume,
+                iteration_timeout: args.iteration_timeout,
+                deadline: args.deadline,
+                on_failure: args.on_failure,
+                scope_policy: args.scope_policy,
+                dry_run: args.dry_run,
+                prompts_dir: args.prompts_dir,
+                selection: types::StorySelection {
+                    stories: args.stories,
+                    from: args.from,
+                    tags: args.tags,
+                    exclude: args.exclude,
+                    exclude_tags: args.exclude_tags,
+                },
+                force: args.force,
+                allow_unenforced_permissions: args.allow_unenforced_permissions,
+                group_by_tag: args.group_by_tag,
+            })
             .await
         }
         Commands::Plan(args) => {
-            workflows::run_plan_command(args.cwd, args.output, args.description, args.force).await
+            workflows::run_plan_command(workflows::PlanOptions {
+                cwd: args.cwd,
+                output: args.output,
+                config: args.config,
+                description: args.description,
+                force: args.force,
+                extract_from: args.extract_from,
+                repair_attempts: args.repair_attempts,
+                amend: args.amend,
+                no_context: args.no_context,
+                allow_unenforced_permissions: args.allow_unenforced_permissions,
+                group_by_tag: args.group_by_tag,
+            })
+            .await
+        }
+        Commands::Logs(args) => {
+            workflows::run_logs_command(args.cwd, args.story, args.run, args.attempt)
+        }
+        Commands::Replay(args) => {
+            workflows::run_replay_command(args.cwd, args.story, args.run, args.attempt)
+        }
+        Commands::Story(args) => {
+            let action = match args.command {
+                StoryCommand::Add { title, id, fields } => workflows::StoryAction::Add {
+                    id,
+                    edit: fields.into_edit(Some(title)),
+                },
+                StoryCommand::Edit {
+                    id,
+                    title,
+                    status,
+                    clear_depends_on,
+                    clear_tags,
+                    clear_scope,
+                    fields,
+                } => workflows::StoryAction::Edit {
+                    id,
+                    edit: types::StoryEdit {
+                        status,
+                        clear_depends_on,
+                        clear_tags,
+                        clear_scope,
+                        ..fields.into_edit(title)
+                    },
+                },
+                StoryCommand::Remove { ids, force } => {
+                    workflows::StoryAction::Remove { ids, force }
+                }
+                StoryCommand::Reorder { ids } => workflows::StoryAction::Reorder { ids },
+                StoryCommand::Reset { ids, tag, all } => workflows::StoryAction::Reset {
+                    selection: types::StorySelection {
+                        stories: ids,
+                        tags: tag,
+                        ..Default::default()
+                    },
+                    all,
+                },
+            };
+            workflows::run_story_command(args.cwd, args.prd, action)
+        }
+        Commands::Status(args) => {
+            workflows::run_status_command(args.cwd, args.prd, args.json, args.group_by_tag)
         }
     }
 }
@@ -1,8 +1,10 @@
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::sync::OnceLock;
 use std::time::Duration;
 
+use crate::types::StoryStatus;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum OutputMode {
     #[default]
@@ -70,22 +72,26 @@ pub fn story_status(id: &str, title: &str, status: StoryStatus) {
     if is_quiet() {
         return;
     }
-    let (icon, style) = match status {
-        StoryStatus::Pending => ("○".dimmed(), title.dimmed()),
-        StoryStatus::Running => ("⚙".yellow().bold(), title.yellow()),
-        StoryStatus::Success => ("✓".green().bold(), title.green()),
-        StoryStatus::Failed => ("✗".red().bold(), title.red()),
+    let style = match status {
+        StoryStatus::Pending | StoryStatus::Skipped => title.dimmed(),
+        StoryStatus::InProgress => title.yellow(),
+        StoryStatus::Passed => title.green(),
+        StoryStatus::Failed | StoryStatus::Blocked => title.red(),
+        StoryStatus::NeedsHuman => title.magenta(),
     };
-    eprintln!("  {} {} {}", icon, id.bold(), style);
-}
-
-#[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
-pub enum StoryStatus {
-    Pending,
-    Running,
-    Success,
-    Failed,
+    eprintln!("  {} {} {}", status_icon(status), id.bold(), style);
+}
+
+pub fn status_icon(status: StoryStatus) -> ColoredString {
+    match status {
+        StoryStatus::Pending => "○".dimmed(),
+        StoryStatus::InProgress => "⚙".yellow().bold(),
+        StoryStatus::Passed => "✓".green().bold(),
+        StoryStatus::Failed => "✗".red().bold(),
+        StoryStatus::Blocked => "⊘".red().bold(),
+        StoryStatus::Skipped => "↷".dimmed(),
+        StoryStatus::NeedsHuman => "?".magenta().bold(),
+    }
 }
 
 pub fn create_spinner(msg: &str) -> ProgressBar {
@@ -104,6 +110,24 @@ pub fn create_spinner(msg: &str) -> ProgressBar {
     spinner
 }
 
+/// Spinner for one of several concurrent jobs, labelled with `prefix` (e.g. a story ID).
+pub fn create_job_spinner(multi: &MultiProgress, prefix: &str, msg: &str) -> ProgressBar {
+    if is_quiet() {
+        return ProgressBar::hidden();
+    }
+    let spinner = multi.add(ProgressBar::new_spinner());
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
+            .template("{spinner:.cyan} {prefix:.bold} {msg}")
+            .unwrap(),
+    );
+    spinner.set_prefix(prefix.to_string());
+    spinner.set_message(msg.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(80));
+    spinner
+}
+
 pub fn create_progress_bar(total: u64) -> ProgressBar {
     if is_quiet() {
         return ProgressBar::hidden();
@@ -119,7 +143,6 @@ pub fn create_progress_bar(total: u64) -> ProgressBar {
     pb
 }
 
-#[allow(dead_code)]
 pub fn create_multi_progress() -> MultiProgress {
     if is_quiet() {
         MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden())
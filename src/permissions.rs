@@ -0,0 +1,150 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Tools that create or change files; `paths` restricts where they may write.
+pub const EDIT_TOOLS: &[&str] = &["edit_file", "create_file", "undo_edit"];
+
+/// Tools that only look at the codebase or the web, allowed in read-only sessions.
+const READ_ONLY_TOOLS: &[&str] = &[
+    "Read",
+    "Grep",
+    "glob",
+    "list_directory",
+    "finder",
+    "read_web_page",
+    "web_search",
+];
+
+/// Which tools an agent may use, from the `permissions` section of the config or a story.
+/// Tool names and patterns follow Amp's permission rules (`*` matches anything).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionPolicy {
+    /// Tools the agent may use; any other tool falls back to `default`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_tools: Option<Vec<String>>,
+    /// Tools the agent may never use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deny_tools: Option<Vec<String>>,
+    /// Shell commands the agent may run, e.g. `cargo *`; any other command is rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_commands: Option<Vec<String>>,
+    /// Shell commands the agent may never run, e.g. `git push*`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deny_commands: Option<Vec<String>>,
+    /// Paths (relative to the working directory) the agent may create or edit files under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paths: Option<Vec<String>>,
+    /// What happens to tool uses no rule covers: `reject` if `allow_tools` is set, else `allow`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<PermissionAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionAction {
+    Allow,
+    Reject,
+}
+
+impl fmt::Display for PermissionAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PermissionAction::Allow => "allow",
+            PermissionAction::Reject => "reject",
+        })
+    }
+}
+
+impl PermissionPolicy {
+    /// Looking around only: no edits, no shell.
+    pub fn read_only() -> Self {
+        Self {
+            allow_tools: Some(READ_ONLY_TOOLS.iter().map(|t| t.to_string()).collect()),
+            default: Some(PermissionAction::Reject),
+            ..Default::default()
+        }
+    }
+
+    /// This policy with every field `overrides` sets replaced by its value.
+    pub fn with_overrides(&self, overrides: &PermissionPolicy) -> Self {
+        Self {
+            allow_tools: overrides
+                .allow_tools
+                .clone()
+                .or_else(|| self.allow_tools.clone()),
+            deny_tools: overrides
+                .deny_tools
+                .clone()
+                .or_else(|| self.deny_tools.clone()),
+            allow_commands: overrides
+                .allow_commands
+                .clone()
+                .or_else(|| self.allow_commands.clone()),
+            deny_commands: overrides
+                .deny_commands
+                .clone()
+                .or_else(|| self.deny_commands.clone()),
+            paths: overrides.paths.clone().or_else(|| self.paths.clone()),
+            default: overrides.default.or(self.default),
+        }
+    }
+
+    /// Action for tool uses no other rule covers.
+    pub fn default_action(&self) -> PermissionAction {
+        self.default.unwrap_or(if self.allow_tools.is_some() {
+            PermissionAction::Reject
+        } else {
+            PermissionAction::Allow
+        })
+    }
+
+    /// True if the policy lets the agent do anything.
+    pub fn is_unrestricted(&self) -> bool {
+        self.deny_tools.is_none()
+            && self.allow_commands.is_none()
+            && self.deny_commands.is_none()
+            && self.paths.is_none()
+            && self.default_action() == PermissionAction::Allow
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let lists = [
+            ("allow_tools", &self.allow_tools),
+            ("deny_tools", &self.deny_tools),
+            ("allow_commands", &self.allow_commands),
+            ("deny_commands", &self.deny_commands),
+            ("paths", &self.paths),
+        ];
+        for (name, patterns) in lists {
+            anyhow::ensure!(
+                patterns.iter().flatten().all(|p| !p.trim().is_empty()),
+                "permissions.{name} contains an empty pattern"
+            );
+        }
+        Ok(())
+    }
+
+    /// One-line summary for run headers, e.g. `deny tools: web_search; other tools: allow`.
+    pub fn describe(&self) -> String {
+        if self.is_unrestricted() {
+            return "all tools allowed".to_string();
+        }
+
+        let mut parts = Vec::new();
+        let lists = [
+            ("allow tools", &self.allow_tools),
+            ("deny tools", &self.deny_tools),
+            ("allow commands", &self.allow_commands),
+            ("deny commands", &self.deny_commands),
+            ("edits limited to", &self.paths),
+        ];
+        for (label, patterns) in lists {
+            if let Some(patterns) = patterns {
+                parts.push(format!("{label}: {}", patterns.join(", ")));
+            }
+        }
+        parts.push(format!("other tools: {}", self.default_action()));
+        parts.join("; ")
+    }
+}
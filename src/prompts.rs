@@ -1,8 +1,8 @@
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::types::Story;
+use crate::types::{Prd, Story, VerifyStep, next_story_id};
 
 // Embedded default resources
 pub const DEFAULT_PROMPT: &str = include_str!("../prompt.md");
@@ -24,6 +24,8 @@ const PLANNING_PROMPT_TEMPLATE: &str = r#"You are an AI planning assistant helpi
    - Clear, focused scope (each story should be completable in one session)
    - Specific acceptance criteria
    - Logical priority ordering
+   - Explicit dependencies on other stories where one needs another's work
+   - A few short tags naming the area each story touches (e.g. backend, frontend, infra, tech-debt), so the work can be split across people and CI jobs
    - Unique story IDs (format: STORY-001, STORY-002, etc.)
 
 4. Refine based on their feedback
@@ -43,6 +45,7 @@ const PLANNING_PROMPT_TEMPLATE: &str = r#"You are an AI planning assistant helpi
 - Ask one question at a time or group related questions
 - Confirm understanding before proposing stories
 - Be open to iteration and refinement
+- Once the stories are agreed, tell the user to type /done to generate the PRD
 
 {initial_context}
 
@@ -60,11 +63,13 @@ const EXTRACTION_PROMPT: &str = r#"You are a structured data extraction assistan
       "title": "Brief title",
       "description": "Detailed description of what needs to be done",
       "priority": 1,
-      "passes": false,
+      "status": "pending",
       "acceptance_criteria": [
         "Specific criterion 1",
         "Specific criterion 2"
-      ]
+      ],
+      "depends_on": [],
+      "tags": ["backend"]
     }}
   ]
 }}
@@ -74,8 +79,10 @@ const EXTRACTION_PROMPT: &str = r#"You are a structured data extraction assistan
 1. Generate a meaningful branch name based on the project
 2. Extract all agreed-upon stories from the conversation
 3. Priority: number from 1 (highest) to N (lowest), ordered by implementation sequence
-4. Set all "passes" to false (work hasn't started yet)
+4. Set every "status" to "pending" (work hasn't started yet)
 5. Acceptance criteria should be specific, testable conditions
+6. List in "depends_on" the IDs of stories that must be finished first (empty if none); never create cycles
+7. Give each story one to three lowercase tags for the area it touches (e.g. "backend", "frontend", "infra", "tech-debt"), reusing the tags discussed in the conversation; tags contain no spaces
 
 ## Output Format
 
@@ -94,7 +101,7 @@ pub fn load_prompt(path: Option<&PathBuf>) -> Result<String> {
     }
 }
 
-pub fn load_progress(path: &PathBuf) -> Result<String> {
+pub fn load_progress(path: &Path) -> Result<String> {
     if path.exists() {
         fs::read_to_string(path)
             .with_context(|| format!("Failed to read progress file: {}", path.display()))
@@ -110,7 +117,37 @@ pub fn append_progress(path: &PathBuf, entry: &str) -> Result<()> {
     fs::write(path, content).context("Failed to write progress.txt")
 }
 
-pub fn build_iteration_prompt(base_prompt: &str, story: &Story, progress: &str) -> String {
+pub fn build_iteration_prompt(
+    base_prompt: &str,
+    story: &Story,
+    progress: &str,
+    verify: &[VerifyStep],
+) -> String {
+    let verification = if verify.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\n### Verification\nramph will run these commands after you finish; the story only passes if all of them succeed:\n{}\n",
+            verify
+                .iter()
+                .map(|s| format!("- {}: `{}`", s.name, s.command))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    };
+
+    let scope = match &story.scope {
+        Some(scope) => format!(
+            "\n### Scope\nOnly create, edit or delete files matching these paths; ramph checks your changes against them and may fail the attempt or revert files outside them (the PRD, the progress log and AGENTS.md are exempt):\n{}\n",
+            scope
+                .iter()
+                .map(|g| format!("- `{g}`"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+        None => String::new(),
+    };
+
     format!(
         r#"{base_prompt}
 
@@ -122,7 +159,7 @@ pub fn build_iteration_prompt(base_prompt: &str, story: &Story, progress: &str)
 
 ### Acceptance Criteria
 {criteria}
-
+{scope}{verification}
 ## Previous Learnings
 {progress}
 
@@ -131,7 +168,7 @@ pub fn build_iteration_prompt(base_prompt: &str, story: &Story, progress: &str)
 1. Implement this story
 2. Run typecheck and tests
 3. If passing, commit with message: "feat({id}): {title}"
-4. Mark the story as done by setting `passes: true` in prd.json
+4. Mark the story as done by setting its `"status"` to `"passed"` in prd.json (change nothing else in that file; ramph reverts any other edit). If you cannot continue without a decision only a person can make, set it to `"needs_human"` instead and explain why in progress.txt
 5. Append learnings to progress.txt
 6. If you discover reusable patterns, update AGENTS.md
 "#,
@@ -152,20 +189,90 @@ pub fn build_iteration_prompt(base_prompt: &str, story: &Story, progress: &str)
     )
 }
 
-pub fn build_planning_prompt(initial_description: Option<String>) -> String {
-    let initial_context = match initial_description {
+pub fn build_planning_prompt(
+    initial_description: Option<String>,
+    existing: Option<&Prd>,
+    codebase: Option<&str>,
+) -> String {
+    let mut initial_context = match initial_description {
         Some(desc) => format!(
             "\n## Initial Project Description\n\n{}\n\nStart by clarifying any questions about this description.",
             desc
         ),
         None => String::new(),
     };
+    if let Some(prd) = existing {
+        initial_context.push_str(&format!(
+            "\n## Existing PRD\n\nThe project already has a PRD (branch `{}`). You are planning additional stories for it; the user may also want to adjust stories that have not passed yet.\n\n{}\n\nNew stories continue the ID sequence at {}. Stories marked passed are done and must not change. Reuse the existing tags where they fit.",
+            prd.branch_name,
+            story_list(prd),
+            next_story_id(prd)
+        ));
+    }
+
+    if let Some(snapshot) = codebase {
+        initial_context.push_str(&format!(
+            "\n## Existing Codebase\n\nThis is a snapshot of the project the stories are for. Fit them to its architecture, languages and test tooling, and read further files if you need more detail.\n\n{}",
+            snapshot
+        ));
+    }
 
     PLANNING_PROMPT_TEMPLATE.replace("{initial_context}", &initial_context)
 }
 
-pub fn build_extraction_prompt(conversation_history: &str) -> String {
-    EXTRACTION_PROMPT.replace("{conversation_history}", conversation_history)
+/// One line per story with its status, for prompts about an existing PRD.
+fn story_list(prd: &Prd) -> String {
+    prd.stories
+        .iter()
+        .map(|s| {
+            let deps = if s.depends_on.is_empty() {
+                String::new()
+            } else {
+                format!(", depends on {}", s.depends_on.join(", "))
+            };
+            let tags = if s.tags.is_empty() {
+                String::new()
+            } else {
+                format!(", tags {}", s.tags.join(", "))
+            };
+            format!(
+                "- {} [{}] {} (priority {}{deps}{tags})",
+                s.id, s.status, s.title, s.priority
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prompt for the next planning turn when the agent cannot continue its own session:
+/// the planning instructions followed by the whole conversation so far.
+pub fn build_planning_followup_prompt(planning_prompt: &str, conversation: &str) -> String {
+    format!(
+        "{planning_prompt}\n\n## Conversation So Far\n\n{conversation}\n\nReply to the user's last message."
+    )
+}
+
+pub fn build_extraction_prompt(conversation_history: &str, existing: Option<&Prd>) -> String {
+    let mut prompt = EXTRACTION_PROMPT.replace("{conversation_history}", conversation_history);
+    if let Some(prd) = existing {
+        let json = serde_json::to_string_pretty(prd).unwrap_or_default();
+        prompt.push_str(&format!(
+            "\n\n## Existing PRD\n\nThe conversation amends this PRD. Output the complete updated PRD: keep its branchName, copy passed stories exactly as they are, keep the IDs of existing stories, and number new stories from {} on.\n\n{json}",
+            next_story_id(prd)
+        ));
+    }
+    prompt
+}
+
+/// Asks for a corrected PRD after `response` to `extraction_prompt` failed with `error`.
+pub fn build_extraction_repair_prompt(
+    extraction_prompt: &str,
+    response: &str,
+    error: &str,
+) -> String {
+    format!(
+        "{extraction_prompt}\n\n## Previous Attempt\n\nYour previous answer was:\n\n{response}\n\nIt was rejected with this error:\n\n{error}\n\nOutput the complete corrected JSON document."
+    )
 }
 
 pub fn clean_json_response(response: &str) -> Result<String> {
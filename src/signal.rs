@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+use crate::output;
+
+/// Two-step shutdown: the first SIGINT/SIGTERM sets the flag so the run can stop cleanly,
+/// the second exits immediately.
+#[derive(Clone, Default)]
+pub struct Interrupt {
+    flag: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Interrupt {
+    /// An interrupt fired by SIGINT/SIGTERM (`Interrupt::default()` only fires through
+    /// `trigger`).
+    pub fn install() -> Self {
+        let interrupt = Self::default();
+
+        let handle = interrupt.clone();
+        tokio::spawn(async move {
+            loop {
+                wait_for_signal().await;
+                if handle.flag.load(Ordering::SeqCst) {
+                    output::error("Aborting.");
+                    std::process::exit(130);
+                }
+                output::warn(
+                    "Interrupt received; stopping after cleanup (press Ctrl-C again to abort)",
+                );
+                handle.trigger();
+            }
+        });
+
+        interrupt
+    }
+
+    /// Sets the flag and wakes everything waiting in `triggered`.
+    pub fn trigger(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the first signal has arrived (immediately if it already has).
+    pub async fn triggered(&self) {
+        let notified = self.notify.notified();
+        if self.is_set() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
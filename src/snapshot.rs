@@ -0,0 +1,498 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::codebase::glob_matches;
+use crate::git;
+use crate::types::{Prd, StoryStatus, changed_fields, load_prd, save_prd};
+
+/// What an iteration did to the repository, judged against the snapshot taken before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IterationChange {
+    /// New commits, and the story was marked as passing
+    CommittedAndPassed,
+    /// New commits, but the story was not marked as passing
+    CommittedNotMarked,
+    /// Files changed, but nothing was committed
+    Uncommitted,
+    /// No commits and no file changes (marking the story alone does not count)
+    NoOp,
+}
+
+impl fmt::Display for IterationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IterationChange::CommittedAndPassed => "committed and passed",
+            IterationChange::CommittedNotMarked => "committed but not marked as passing",
+            IterationChange::Uncommitted => "uncommitted changes",
+            IterationChange::NoOp => "no-op",
+        })
+    }
+}
+
+/// What an iteration did to the working tree and the story, as found by
+/// `Snapshot::classify`. How the attempt as a whole ended is decided from this afterwards.
+pub struct IterationClassification {
+    pub change: IterationChange,
+    pub commits: usize,
+    /// Files whose uncommitted state changed during the iteration
+    pub changed_files: Vec<String>,
+    /// The agent set the story to `needs_human`
+    pub needs_human: bool,
+    /// HEAD after the iteration
+    pub head: String,
+}
+
+impl IterationClassification {
+    /// One-line description for the progress log, e.g. `committed and passed (2 commits)`.
+    pub fn describe(&self) -> String {
+        match self.change {
+            IterationChange::CommittedAndPassed | IterationChange::CommittedNotMarked => {
+                format!("{} ({} commit(s))", self.change, self.commits)
+            }
+            IterationChange::Uncommitted => {
+                format!("{} ({})", self.change, self.changed_files.join(", "))
+            }
+            IterationChange::NoOp => self.change.to_string(),
+        }
+    }
+}
+
+/// Git and PRD state of a working directory, captured before an iteration starts.
+pub struct Snapshot {
+    pub head: String,
+    /// Uncommitted files mapped to a hash of their content (`None` if deleted)
+    dirty: BTreeMap<String, Option<u64>>,
+    /// Content of the uncommitted files (`None` if deleted), put back after a reset so a
+    /// run started with `--allow-dirty` doesn't lose the user's own edits
+    dirty_backup: BTreeMap<String, Option<Vec<u8>>>,
+    story_passed: bool,
+    /// The PRD file's content before the iteration, kept to undo the agent's edits to it
+    prd_backup: Option<String>,
+    /// Files ramph manages itself (PRD, progress log), relative to the working directory
+    managed: Vec<PathBuf>,
+}
+
+impl Snapshot {
+    pub fn capture(
+        workdir: &Path,
+        prd_path: &Path,
+        progress_path: &Path,
+        story_id: &str,
+    ) -> Result<Self> {
+        let managed: Vec<PathBuf> = [prd_path, progress_path]
+            .iter()
+            .filter_map(|p| p.strip_prefix(workdir).ok().map(Path::to_path_buf))
+            .collect();
+
+        let dirty = dirty_state(workdir, &managed)?;
+        let dirty_backup = dirty
+            .keys()
+            .map(|path| (path.clone(), fs::read(workdir.join(path)).ok()))
+            .collect();
+
+        Ok(Self {
+            head: git::head_commit(workdir)?,
+            dirty,
+            dirty_backup,
+            story_passed: story_status(prd_path, story_id) == Some(StoryStatus::Passed),
+            prd_backup: fs::read_to_string(prd_path).ok(),
+            managed,
+        })
+    }
+
+    /// Compares the current state of `workdir` against the snapshot.
+    pub fn classify(
+        &self,
+        workdir: &Path,
+        prd_path: &Path,
+        story_id: &str,
+    ) -> Result<IterationClassification> {
+        let commits = git::commit_count_since(workdir, &self.head)?;
+        self.classify_commits(workdir, prd_path, story_id, commits)
+    }
+
+    /// Like `classify`, but commits that leave nothing behind outside ramph's own files
+    /// don't count, as after their changes were reverted.
+    pub fn classify_net(
+        &self,
+        workdir: &Path,
+        prd_path: &Path,
+        story_id: &str,
+    ) -> Result<IterationClassification> {
+        let mut commits = git::commit_count_since(workdir, &self.head)?;
+        if commits > 0
+            && git::changed_paths(workdir, &self.head, &[])?
+                .iter()
+                .all(|path| self.managed.iter().any(|m| m == Path::new(path)))
+        {
+            commits = 0;
+        }
+        self.classify_commits(workdir, prd_path, story_id, commits)
+    }
+
+    fn classify_commits(
+        &self,
+        workdir: &Path,
+        prd_path: &Path,
+        story_id: &str,
+        commits: usize,
+    ) -> Result<IterationClassification> {
+        let after = dirty_state(workdir, &self.managed)?;
+
+        let mut changed_files: Vec<String> = after
+            .iter()
+            .filter(|(path, hash)| self.dirty.get(*path) != Some(hash))
+            .map(|(path, _)| path.clone())
+            .collect();
+        // Files that were dirty before and are clean now were reverted or committed
+        changed_files.extend(
+            self.dirty
+                .keys()
+                .filter(|path| !after.contains_key(*path))
+                .cloned(),
+        );
+        changed_files.sort();
+
+        let status = story_status(prd_path, story_id);
+        let marked = !self.story_passed && status == Some(StoryStatus::Passed);
+        let change = if commits > 0 && marked {
+            IterationChange::CommittedAndPassed
+        } else if commits > 0 {
+            IterationChange::CommittedNotMarked
+        } else if !changed_files.is_empty() {
+            IterationChange::Uncommitted
+        } else {
+            IterationChange::NoOp
+        };
+
+        Ok(IterationClassification {
+            change,
+            commits,
+            changed_files,
+            needs_human: status == Some(StoryStatus::NeedsHuman),
+            head: git::head_commit(workdir)?,
+        })
+    }
+
+    /// Undoes any edit the agent made to the PRD other than setting the current story's
+    /// status to `passed` or `needs_human`, restoring the pre-iteration copy if the file no
+    /// longer parses. Returns a description of what was repaired.
+    pub fn protect_prd(&self, prd_path: &Path, story_id: &str) -> Result<Option<String>> {
+        let Some(backup) = &self.prd_backup else {
+            return Ok(None);
+        };
+        let before: Prd = match serde_json::from_str(backup) {
+            Ok(prd) => prd,
+            Err(_) => return Ok(None),
+        };
+
+        let after = match fs::read_to_string(prd_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(serde_json::from_str::<Prd>(&content)?))
+        {
+            Ok(prd) => prd,
+            Err(e) => {
+                fs::write(prd_path, backup)
+                    .with_context(|| format!("Failed to restore {}", prd_path.display()))?;
+                return Ok(Some(format!(
+                    "file no longer parsed ({e}); restored the pre-iteration copy"
+                )));
+            }
+        };
+
+        let mut allowed = before.clone();
+        let marked = after
+            .stories
+            .iter()
+            .find(|s| s.id == story_id)
+            .map(|s| s.status)
+            .filter(|status| matches!(status, StoryStatus::Passed | StoryStatus::NeedsHuman));
+        if let (Some(story), Some(status)) = (
+            allowed.stories.iter_mut().find(|s| s.id == story_id),
+            marked,
+        ) {
+            story.status = status;
+        }
+
+        let edits = prd_edits(&allowed, &after);
+        if edits.is_empty() {
+            return Ok(None);
+        }
+        save_prd(prd_path, &allowed)?;
+        Ok(Some(format!("reverted agent edits: {}", edits.join("; "))))
+    }
+
+    /// Files changed since the snapshot, committed or not, that match none of the `scope`
+    /// globs. ramph's own files and AGENTS.md are always in scope.
+    pub fn out_of_scope(
+        &self,
+        workdir: &Path,
+        iteration: &IterationClassification,
+        scope: &[String],
+    ) -> Result<Vec<String>> {
+        let mut files = git::changed_paths(workdir, &self.head, &[])?;
+        files.extend(iteration.changed_files.iter().cloned());
+        files.retain(|path| {
+            !self.managed.iter().any(|m| m == Path::new(path))
+                && !glob_matches("AGENTS.md", path)
+                && !scope.iter().any(|g| glob_matches(g, path))
+        });
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// Hard resets `workdir` to the snapshot's HEAD and deletes untracked files created since,
+    /// keeping ramph's own files as they are now and putting back the uncommitted changes
+    /// that were there before the iteration.
+    pub fn reset(&self, workdir: &Path) -> Result<()> {
+        let managed: Vec<(PathBuf, Option<Vec<u8>>)> = self
+            .managed
+            .iter()
+            .map(|rel| (workdir.join(rel), fs::read(workdir.join(rel)).ok()))
+            .collect();
+
+        git::reset_hard(workdir, &self.head)?;
+
+        for path in git::dirty_files(workdir)? {
+            if !self.dirty.contains_key(&path)
+                && !self.managed.iter().any(|m| m == Path::new(&path))
+            {
+                let _ = fs::remove_file(workdir.join(&path));
+            }
+        }
+
+        for (path, content) in managed {
+            if let Some(content) = content {
+                fs::write(&path, content)
+                    .with_context(|| format!("Failed to restore {}", path.display()))?;
+            }
+        }
+
+        for (path, content) in &self.dirty_backup {
+            let path = workdir.join(path);
+            match content {
+                Some(content) => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&path, content)
+                        .with_context(|| format!("Failed to restore {}", path.display()))?;
+                }
+                None => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes how `after` differs from `before`, story by story.
+fn prd_edits(before: &Prd, after: &Prd) -> Vec<String> {
+    let mut edits = Vec::new();
+    if before.branch_name != after.branch_name {
+        edits.push("branchName changed".to_string());
+    }
+
+    let ids = |prd: &Prd| -> Vec<String> { prd.stories.iter().map(|s| s.id.clone()).collect() };
+    let (before_ids, after_ids) = (ids(before), ids(after));
+    let added: Vec<&str> = after_ids
+        .iter()
+        .filter(|id| !before_ids.contains(id))
+        .map(String::as_str)
+        .collect();
+    let removed: Vec<&str> = before_ids
+        .iter()
+        .filter(|id| !after_ids.contains(id))
+        .map(String::as_str)
+        .collect();
+    if !added.is_empty() {
+        edits.push(format!("added {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        edits.push(format!("removed {}", removed.join(", ")));
+    }
+
+    let kept: Vec<&String> = after_ids
+        .iter()
+        .filter(|id| before_ids.contains(id))
+        .collect();
+    let original_order: Vec<&String> = before_ids
+        .iter()
+        .filter(|id| after_ids.contains(id))
+        .collect();
+    if kept != original_order {
+        edits.push("reordered stories".to_string());
+    }
+
+    for old in &before.stories {
+        let Some(new) = after.stories.iter().find(|s| s.id == old.id) else {
+            continue;
+        };
+        let fields = changed_fields(old, new);
+        if !fields.is_empty() {
+            edits.push(format!("changed {} of {}", fields.join(", "), old.id));
+        }
+    }
+
+    edits
+}
+
+fn story_status(prd_path: &Path, story_id: &str) -> Option<StoryStatus> {
+    let prd = load_prd(prd_path).ok()?;
+    prd.stories
+        .iter()
+        .find(|s| s.id == story_id)
+        .map(|s| s.status)
+}
+
+fn dirty_state(workdir: &Path, managed: &[PathBuf]) -> Result<BTreeMap<String, Option<u64>>> {
+    Ok(git::dirty_files(workdir)?
+        .into_iter()
+        .filter(|path| !managed.iter().any(|m| m == Path::new(path)))
+        .map(|path| {
+            let hash = fs::read(workdir.join(&path)).ok().map(|content| {
+                let mut hasher = DefaultHasher::new();
+                content.hash(&mut hasher);
+                hasher.finish()
+            });
+            (path, hash)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::GitRepo;
+
+    /// A repository with a two-story PRD committed, and a snapshot taken for story A.
+    fn setup() -> (GitRepo, Snapshot) {
+        let repo = GitRepo::new();
+        let prd = serde_json::json!({
+            "branchName": "feature/test",
+            "stories": [
+                {"id": "A", "title": "Story A", "description": "Do A", "priority": 1,
+                 "acceptance_criteria": ["A works"]},
+                {"id": "B", "title": "Story B", "description": "Do B", "priority": 2,
+                 "acceptance_criteria": ["B works"]},
+            ],
+        });
+        repo.write_file("prd.json", &serde_json::to_string_pretty(&prd).unwrap());
+        repo.write_file("src.txt", "original\n");
+        repo.git(&["add", "-A"]);
+        repo.git(&["commit", "-q", "-m", "setup"]);
+
+        let snapshot = Snapshot::capture(
+            &repo.dir,
+            &repo.dir.join("prd.json"),
+            &repo.dir.join("progress.txt"),
+            "A",
+        )
+        .unwrap();
+        (repo, snapshot)
+    }
+
+    fn edit_prd(repo: &GitRepo, edit: impl FnOnce(&mut Prd)) {
+        let path = repo.dir.join("prd.json");
+        let mut prd = load_prd(&path).unwrap();
+        edit(&mut prd);
+        save_prd(&path, &prd).unwrap();
+    }
+
+    fn protect(repo: &GitRepo, snapshot: &Snapshot) -> Option<String> {
+        snapshot
+            .protect_prd(&repo.dir.join("prd.json"), "A")
+            .unwrap()
+    }
+
+    fn classify(repo: &GitRepo, snapshot: &Snapshot) -> IterationChange {
+        snapshot
+            .classify(&repo.dir, &repo.dir.join("prd.json"), "A")
+            .unwrap()
+            .change
+    }
+
+    #[test]
+    fn protect_prd_reverts_edits_to_other_stories() {
+        let (repo, snapshot) = setup();
+        edit_prd(&repo, |prd| {
+            prd.stories[1].acceptance_criteria = vec!["Anything goes".to_string()];
+        });
+
+        let repaired = protect(&repo, &snapshot).unwrap();
+        assert!(repaired.contains("acceptance_criteria of B"), "{repaired}");
+        let prd = load_prd(&repo.dir.join("prd.json")).unwrap();
+        assert_eq!(prd.stories[1].acceptance_criteria, ["B works"]);
+    }
+
+    #[test]
+    fn protect_prd_reverts_reordering() {
+        let (repo, snapshot) = setup();
+        edit_prd(&repo, |prd| prd.stories.reverse());
+
+        let repaired = protect(&repo, &snapshot).unwrap();
+        assert!(repaired.contains("reordered stories"), "{repaired}");
+        let prd = load_prd(&repo.dir.join("prd.json")).unwrap();
+        let ids: Vec<&str> = prd.stories.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["A", "B"]);
+    }
+
+    #[test]
+    fn protect_prd_keeps_the_current_story_status() {
+        for status in [StoryStatus::Passed, StoryStatus::NeedsHuman] {
+            let (repo, snapshot) = setup();
+            edit_prd(&repo, |prd| prd.stories[0].status = status);
+            assert_eq!(protect(&repo, &snapshot), None);
+
+            // Alongside a disallowed edit, only the disallowed part is reverted
+            edit_prd(&repo, |prd| prd.stories[1].title = "Renamed".to_string());
+            assert!(protect(&repo, &snapshot).is_some());
+            let prd = load_prd(&repo.dir.join("prd.json")).unwrap();
+            assert_eq!(prd.stories[0].status, status);
+            assert_eq!(prd.stories[1].title, "Story B");
+        }
+    }
+
+    #[test]
+    fn protect_prd_restores_an_unparseable_file() {
+        let (repo, snapshot) = setup();
+        let before = repo.read_file("prd.json");
+        repo.write_file("prd.json", "{ not json");
+
+        let repaired = protect(&repo, &snapshot).unwrap();
+        assert!(repaired.contains("no longer parsed"), "{repaired}");
+        assert_eq!(repo.read_file("prd.json"), before);
+    }
+
+    #[test]
+    fn classify_tells_iteration_changes_apart() {
+        let (repo, snapshot) = setup();
+        // Marking the story alone is not progress
+        edit_prd(&repo, |prd| prd.stories[0].status = StoryStatus::Passed);
+        assert_eq!(classify(&repo, &snapshot), IterationChange::NoOp);
+
+        let (repo, snapshot) = setup();
+        repo.write_file("src.txt", "changed\n");
+        assert_eq!(classify(&repo, &snapshot), IterationChange::Uncommitted);
+
+        let (repo, snapshot) = setup();
+        repo.write_file("src.txt", "changed\n");
+        repo.git(&["commit", "-qam", "work"]);
+        assert_eq!(
+            classify(&repo, &snapshot),
+            IterationChange::CommittedNotMarked
+        );
+
+        edit_prd(&repo, |prd| prd.stories[0].status = StoryStatus::Passed);
+        assert_eq!(
+            classify(&repo, &snapshot),
+            IterationChange::CommittedAndPassed
+        );
+    }
+}
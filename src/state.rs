@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::ramph_dir;
+
+const STATE_FILE: &str = "state.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Interrupted,
+    Finished,
+}
+
+/// Bookkeeping for the current (or last) `ramph run`, persisted to `.ramph/state.json`
+/// so an interrupted run can be resumed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunState {
+    pub run_id: String,
+    pub status: RunStatus,
+    pub branch: String,
+    pub start_commit: String,
+    /// Number of iterations started so far
+    pub iteration: usize,
+    /// Stories with an attempt in flight
+    #[serde(default)]
+    pub current_stories: Vec<String>,
+    /// Agent session IDs per story, oldest first
+    #[serde(default)]
+    pub sessions: BTreeMap<String, Vec<String>>,
+    pub started_at: String,
+    pub updated_at: String,
+}
+
+impl RunState {
+    pub fn new(branch: &str, start_commit: &str) -> Self {
+        let now = Local::now();
+        Self {
+            run_id: now.format("%Y%m%d-%H%M%S").to_string(),
+            status: RunStatus::Running,
+            branch: branch.to_string(),
+            start_commit: start_commit.to_string(),
+            iteration: 0,
+            current_stories: Vec::new(),
+            sessions: BTreeMap::new(),
+            started_at: now.to_rfc3339(),
+            updated_at: now.to_rfc3339(),
+        }
+    }
+
+    pub fn last_session(&self, story_id: &str) -> Option<&str> {
+        self.sessions
+            .get(story_id)
+            .and_then(|s| s.last())
+            .map(String::as_str)
+    }
+
+    pub fn record_session(&mut self, story_id: &str, session_id: &str) {
+        let sessions = self.sessions.entry(story_id.to_string()).or_default();
+        if sessions.last().map(String::as_str) != Some(session_id) {
+            sessions.push(session_id.to_string());
+        }
+    }
+}
+
+pub fn state_path(cwd: &Path) -> PathBuf {
+    cwd.join(crate::config::RAMPH_DIR).join(STATE_FILE)
+}
+
+pub fn load_state(cwd: &Path) -> Result<Option<RunState>> {
+    let path = state_path(cwd);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read run state: {}", path.display()))?;
+    let state = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse run state: {}", path.display()))?;
+    Ok(Some(state))
+}
+
+pub fn save_state(cwd: &Path, state: &mut RunState) -> Result<()> {
+    state.updated_at = Local::now().to_rfc3339();
+    let path = ramph_dir(cwd)?.join(STATE_FILE);
+    let content = serde_json::to_string_pretty(state)?;
+    fs::write(&path, content)
+        .with_context(|| format!("Failed to write run state: {}", path.display()))
+}
@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::backend::AgentEvent;
+use crate::config::{RAMPH_DIR, ramph_dir};
+
+const RUNS_DIR: &str = "runs";
+
+/// One line of a transcript file: an agent event and when it arrived.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// RFC 3339 time the event arrived
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AgentEvent,
+}
+
+/// Appends agent events to `.ramph/runs/<run-id>/<story>/<attempt>.jsonl` as they arrive,
+/// so a transcript survives the attempt being cancelled.
+pub struct TranscriptWriter {
+    file: File,
+}
+
+impl TranscriptWriter {
+    /// Opens `path` for appending. A resumed attempt has a new number, so it gets a file
+    /// of its own next to the interrupted one's.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open transcript {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, event: &AgentEvent) -> Result<()> {
+        let entry = TranscriptEntry {
+            timestamp: Local::now().to_rfc3339(),
+            event: event.clone(),
+        };
+        let line = serde_json::to_string(&entry)?;
+        writeln!(self.file, "{line}").context("Failed to write transcript")
+    }
+}
+
+/// A saved transcript of one story attempt.
+pub struct AttemptTranscript {
+    pub run_id: String,
+    pub story_id: String,
+    pub attempt: u32,
+    pub path: PathBuf,
+}
+
+/// Where the transcript of `story_id`'s `attempt` in run `run_id` is kept.
+pub fn transcript_path(cwd: &Path, run_id: &str, story_id: &str, attempt: u32) -> Result<PathBuf> {
+    Ok(ramph_dir(cwd)?
+        .join(RUNS_DIR)
+        .join(run_id)
+        .join(story_id)
+        .join(format!("{attempt}.jsonl")))
+}
+
+pub fn read_transcript(path: &Path) -> Result<Vec<TranscriptEntry>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read transcript {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid transcript entry", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// Transcripts of `story_id`, oldest attempt first. Looks in run `run_id` if given,
+/// otherwise in the most recent run that has any for the story.
+pub fn find_transcripts(
+    cwd: &Path,
+    story_id: &str,
+    run_id: Option<&str>,
+    attempt: Option<u32>,
+) -> Result<Vec<AttemptTranscript>> {
+    let runs_dir = cwd.join(RAMPH_DIR).join(RUNS_DIR);
+    let runs = match run_id {
+        Some(run_id) => vec![run_id.to_string()],
+        None => {
+            let mut runs: Vec<String> = fs::read_dir(&runs_dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.path().is_dir())
+                        .map(|entry| entry.file_name().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            // Run IDs are timestamps, so the newest sorts last
+            runs.sort();
+            runs.reverse();
+            runs
+        }
+    };
+
+    for run in &runs {
+        let story_dir = runs_dir.join(run).join(story_id);
+        let Ok(entries) = fs::read_dir(&story_dir) else {
+            continue;
+        };
+        let mut transcripts: Vec<AttemptTranscript> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let number = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_suffix(".jsonl")?
+                    .parse()
+                    .ok()?;
+                Some(AttemptTranscript {
+                    run_id: run.clone(),
+                    story_id: story_id.to_string(),
+                    attempt: number,
+                    path,
+                })
+            })
+            .filter(|t| attempt.is_none_or(|a| a == t.attempt))
+            .collect();
+        if transcripts.is_empty() {
+            continue;
+        }
+        transcripts.sort_by_key(|t| t.attempt);
+        return Ok(transcripts);
+    }
+
+    let attempt = attempt.map(|a| format!(" attempt {a}")).unwrap_or_default();
+    match run_id {
+        Some(run_id) => anyhow::bail!("No transcript for {story_id}{attempt} in run {run_id}"),
+        None => anyhow::bail!(
+            "No transcript for {story_id}{attempt} under {}",
+            runs_dir.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::GitRepo;
+
+    fn write(cwd: &Path, run_id: &str, attempt: u32, events: &[AgentEvent]) {
+        let path = transcript_path(cwd, run_id, "A", attempt).unwrap();
+        let mut writer = TranscriptWriter::open(&path).unwrap();
+        for event in events {
+            writer.record(event).unwrap();
+        }
+    }
+
+    #[test]
+    fn transcripts_read_back_what_was_written() {
+        let repo = GitRepo::new();
+        let events = vec![
+            AgentEvent::Prompt {
+                text: "Do A".to_string(),
+            },
+            AgentEvent::Raw {
+                message: serde_json::json!({"type": "assistant", "extra": [1, 2]}),
+            },
+            AgentEvent::Text {
+                text: "Done".to_string(),
+            },
+            AgentEvent::Result {
+                is_error: false,
+                error: None,
+                duration_ms: 1200,
+                num_turns: 3,
+            },
+        ];
+        write(&repo.dir, "20260101-000000", 1, &events[..1]);
+        write(&repo.dir, "20260102-000000", 1, &events);
+        write(&repo.dir, "20260102-000000", 2, &events[..2]);
+
+        let transcripts = find_transcripts(&repo.dir, "A", None, None).unwrap();
+        let found: Vec<(&str, u32)> = transcripts
+            .iter()
+            .map(|t| (t.run_id.as_str(), t.attempt))
+            .collect();
+        assert_eq!(found, [("20260102-000000", 1), ("20260102-000000", 2)]);
+
+        let read: Vec<serde_json::Value> = read_transcript(&transcripts[0].path)
+            .unwrap()
+            .iter()
+            .map(|entry| serde_json::to_value(&entry.event).unwrap())
+            .collect();
+        let written: Vec<serde_json::Value> = events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+        assert_eq!(read, written);
+
+        let older = find_transcripts(&repo.dir, "A", Some("20260101-000000"), None).unwrap();
+        assert_eq!(older.len(), 1);
+        let second = find_transcripts(&repo.dir, "A", None, Some(2)).unwrap();
+        assert_eq!(second[0].attempt, 2);
+        assert!(find_transcripts(&repo.dir, "B", None, None).is_err());
+    }
+}
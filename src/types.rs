@@ -1,20 +1,26 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::Path;
+use std::time::Duration;
 
+use crate::config::{format_duration, parse_duration};
 use crate::output;
+use crate::permissions::PermissionPolicy;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "PrdFile")]
 pub struct Prd {
     #[serde(rename = "branchName")]
     pub branch_name: String,
     pub stories: Vec<Story>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Story {
     pub id: String,
     pub title: String,
@@ -22,26 +28,540 @@ pub struct Story {
     #[serde(default)]
     pub priority: i32,
     #[serde(default)]
-    pub passes: bool,
+    pub status: StoryStatus,
+    /// When the first attempt at this story started (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    /// When the story last moved to a status other than `pending` or `in_progress`, so a
+    /// failed attempt stamps it too and the next attempt clears it (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    #[serde(default)]
+    pub acceptance_criteria: Vec<String>,
+    /// IDs of stories that must pass before this one can start
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Free-form labels (e.g. "backend", "infra") used to select stories
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Verification steps for this story, replacing the project-level ones from the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<Vec<VerifyStep>>,
+    /// Attempts this story gets before it is blocked (overrides the global default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u32>,
+    /// Wall-clock limit for each attempt at this story, e.g. "45m" (overrides --iteration-timeout)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    /// Tool permissions for this story, overriding the matching fields of the project policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<PermissionPolicy>,
+    /// Globs of the files this story may change, e.g. "src/auth/**"; unrestricted if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub attempt_count: u32,
+    /// Every attempt ramph has made at this story, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Where a story stands.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum StoryStatus {
+    #[default]
+    Pending,
+    /// An attempt is running, or was when ramph last stopped
+    InProgress,
+    Passed,
+    /// The last attempt failed; the story is retried while it has attempts left
+    Failed,
+    /// The retry budget is spent
+    Blocked,
+    /// Left out of runs on purpose
+    Skipped,
+    /// The agent stopped to ask for a decision only a person can make
+    NeedsHuman,
+}
+
+impl StoryStatus {
+    pub const ALL: [StoryStatus; 7] = [
+        StoryStatus::Pending,
+        StoryStatus::InProgress,
+        StoryStatus::Passed,
+        StoryStatus::Failed,
+        StoryStatus::Blocked,
+        StoryStatus::Skipped,
+        StoryStatus::NeedsHuman,
+    ];
+
+    /// Whether the scheduler may pick a story in this state.
+    pub fn is_runnable(self) -> bool {
+        matches!(
+            self,
+            StoryStatus::Pending | StoryStatus::InProgress | StoryStatus::Failed
+        )
+    }
+
+    /// States that only change when someone intervenes; stories depending on a story in
+    /// one of them cannot run.
+    pub fn is_stuck(self) -> bool {
+        matches!(
+            self,
+            StoryStatus::Blocked | StoryStatus::Skipped | StoryStatus::NeedsHuman
+        )
+    }
+}
+
+impl fmt::Display for StoryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StoryStatus::Pending => "pending",
+            StoryStatus::InProgress => "in_progress",
+            StoryStatus::Passed => "passed",
+            StoryStatus::Failed => "failed",
+            StoryStatus::Blocked => "blocked",
+            StoryStatus::Skipped => "skipped",
+            StoryStatus::NeedsHuman => "needs_human",
+        })
+    }
+}
+
+/// One attempt at a story, as recorded in the PRD.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attempt {
+    /// Attempt number, counting every attempt at the story; a resumed attempt gets the
+    /// next number, with the interrupted one kept as its own entry
+    pub number: u32,
+    /// RFC 3339 time the attempt started
+    pub started_at: String,
+    /// RFC 3339 time the attempt ended
+    pub finished_at: String,
+    pub outcome: AttemptOutcome,
+    /// Why the attempt did not pass, shortened
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// HEAD after the attempt, if the agent committed anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_turns: Option<u32>,
+    pub duration_ms: u64,
+}
+
+/// How an attempt ended. Why it did not pass goes into `Attempt::error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptOutcome {
+    Passed,
+    Failed,
+    TimedOut,
+    /// The agent set the story to `needs_human`
+    NeedsHuman,
+    Interrupted,
+}
+
+impl fmt::Display for AttemptOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AttemptOutcome::Passed => "passed",
+            AttemptOutcome::Failed => "failed",
+            AttemptOutcome::TimedOut => "timed out",
+            AttemptOutcome::NeedsHuman => "needs human",
+            AttemptOutcome::Interrupted => "interrupted",
+        })
+    }
+}
+
+impl Story {
+    pub fn passes(&self) -> bool {
+        self.status == StoryStatus::Passed
+    }
+
+    /// Number for the story's next entry in `attempts`. Unlike `attempt_count`, which a
+    /// reset puts back to zero, it keeps increasing over the story's whole history.
+    pub fn next_attempt_number(&self) -> u32 {
+        self.attempts.iter().map(|a| a.number).max().unwrap_or(0) + 1
+    }
+
+    /// Moves the story to `status`, stamping when it started or finished.
+    pub fn set_status(&mut self, status: StoryStatus) {
+        let now = Local::now().to_rfc3339();
+        match status {
+            StoryStatus::Pending => {
+                self.started_at = None;
+                self.finished_at = None;
+            }
+            StoryStatus::InProgress => {
+                self.started_at.get_or_insert(now);
+                self.finished_at = None;
+            }
+            _ => self.finished_at = Some(now),
+        }
+        self.status = status;
+    }
+}
+
+/// The PRD as stored on disk, which may predate `status`: older files mark progress with
+/// `passes` and `blocked` flags instead.
+#[derive(Deserialize)]
+struct PrdFile {
+    #[serde(rename = "branchName")]
+    branch_name: String,
+    stories: Vec<StoryFile>,
+}
+
+#[derive(Deserialize)]
+struct StoryFile {
+    #[serde(flatten)]
+    story: Story,
+    #[serde(default)]
+    status: Option<StoryStatus>,
     #[serde(default)]
+    passes: Option<bool>,
+    #[serde(default)]
+    blocked: Option<bool>,
+}
+
+impl From<PrdFile> for Prd {
+    fn from(file: PrdFile) -> Self {
+        let stories = file
+            .stories
+            .into_iter()
+            .map(|entry| {
+                let mut story = entry.story;
+                story.status = match (entry.status, entry.passes, entry.blocked) {
+                    // Agents used to mark a story done with `passes: true`; still honor it
+                    (_, Some(true), _) => StoryStatus::Passed,
+                    (Some(status), _, _) => status,
+                    (None, _, Some(true)) => StoryStatus::Blocked,
+                    (None, _, _) if story.attempt_count > 0 => StoryStatus::Failed,
+                    (None, _, _) => StoryStatus::Pending,
+                };
+                story
+            })
+            .collect();
+        Prd {
+            branch_name: file.branch_name,
+            stories,
+        }
+    }
+}
+
+/// Filters that narrow which stories a run may pick. Empty filters match everything.
+#[derive(Debug, Default)]
+pub struct StorySelection {
+    pub stories: Vec<String>,
+    /// Skip stories scheduled before this one
+    pub from: Option<String>,
+    pub tags: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Leave out stories carrying any of these tags
+    pub exclude_tags: Vec<String>,
+}
+
+impl StorySelection {
+    pub fn is_empty(&self) -> bool {
+        self.stories.is_empty()
+            && self.from.is_none()
+            && self.tags.is_empty()
+            && self.exclude.is_empty()
+            && self.exclude_tags.is_empty()
+    }
+}
+
+/// Field changes made with `ramph story add` or `ramph story edit`. Unset fields are left
+/// alone; a list that is given replaces the story's list.
+#[derive(Debug, Default)]
+pub struct StoryEdit {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub priority: Option<i32>,
     pub acceptance_criteria: Vec<String>,
+    pub depends_on: Vec<String>,
+    pub tags: Vec<String>,
+    pub scope: Vec<String>,
+    pub max_attempts: Option<u32>,
+    pub timeout: Option<String>,
+    pub status: Option<StoryStatus>,
+    pub clear_depends_on: bool,
+    pub clear_tags: bool,
+    pub clear_scope: bool,
+}
+
+impl StoryEdit {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.priority.is_none()
+            && self.acceptance_criteria.is_empty()
+            && self.depends_on.is_empty()
+            && self.tags.is_empty()
+            && self.scope.is_empty()
+            && self.max_attempts.is_none()
+            && self.timeout.is_none()
+            && self.status.is_none()
+            && !self.clear_depends_on
+            && !self.clear_tags
+            && !self.clear_scope
+    }
+
+    pub fn apply(self, story: &mut Story) {
+        if self.clear_depends_on {
+            story.depends_on.clear();
+        }
+        if self.clear_tags {
+            story.tags.clear();
+        }
+        if self.clear_scope {
+            story.scope = None;
+        }
+        if let Some(title) = self.title {
+            story.title = title;
+        }
+        if let Some(description) = self.description {
+            story.description = description;
+        }
+        if let Some(priority) = self.priority {
+            story.priority = priority;
+        }
+        if !self.acceptance_criteria.is_empty() {
+            story.acceptance_criteria = self.acceptance_criteria;
+        }
+        if !self.depends_on.is_empty() {
+            story.depends_on = self.depends_on;
+        }
+        if !self.tags.is_empty() {
+            story.tags = self.tags;
+        }
+        if !self.scope.is_empty() {
+            story.scope = Some(self.scope);
+        }
+        if self.max_attempts.is_some() {
+            story.max_attempts = self.max_attempts;
+        }
+        if self.timeout.is_some() {
+            story.timeout = self.timeout;
+        }
+        if let Some(status) = self.status {
+            story.set_status(status);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VerifyStep {
+    pub name: String,
+    pub command: String,
 }
 
 impl Prd {
-    pub fn get_next_story(&self) -> Option<&Story> {
+    /// Stories that can start now, in scheduling order (priority, then file order).
+    pub fn ready_stories(&self) -> Vec<&Story> {
+        let mut ready: Vec<&Story> = self
+            .stories
+            .iter()
+            .filter(|s| s.status.is_runnable() && self.dependencies_met(s))
+            .collect();
+        ready.sort_by_key(|s| s.priority);
+        ready
+    }
+
+    pub fn dependencies_met(&self, story: &Story) -> bool {
+        story
+            .depends_on
+            .iter()
+            .all(|dep| self.stories.iter().any(|s| &s.id == dep && s.passes()))
+    }
+
+    /// Puts the given stories back to pending, clearing their attempt count. Returns the IDs
+    /// that changed.
+    pub fn reset_stories(&mut self, ids: &HashSet<String>) -> Vec<String> {
+        let mut reset = Vec::new();
+        for story in self.stories.iter_mut().filter(|s| ids.contains(&s.id)) {
+            if story.status != StoryStatus::Pending || story.attempt_count > 0 {
+                story.set_status(StoryStatus::Pending);
+                story.attempt_count = 0;
+                reset.push(story.id.clone());
+            }
+        }
+        reset
+    }
+
+    /// Stories outside `ids` that depend on one of them.
+    pub fn dependents_of(&self, ids: &HashSet<String>) -> Vec<&Story> {
         self.stories
             .iter()
-            .filter(|s| !s.passes)
-            .min_by_key(|s| s.priority)
+            .filter(|s| !ids.contains(&s.id) && s.depends_on.iter().any(|d| ids.contains(d)))
+            .collect()
+    }
+
+    /// Deletes the stories in `ids` and drops them from the `depends_on` of the rest.
+    pub fn remove_stories(&mut self, ids: &HashSet<String>) {
+        self.stories.retain(|s| !ids.contains(&s.id));
+        for story in &mut self.stories {
+            story.depends_on.retain(|d| !ids.contains(d));
+        }
+    }
+
+    /// Renumbers priorities 1, 2, 3... in scheduling order, with the stories in `first` moved
+    /// to the front in the order given, and sorts the story list to match.
+    pub fn renumber(&mut self, first: &[String]) -> Result<()> {
+        let mut seen = HashSet::new();
+        for id in first {
+            anyhow::ensure!(
+                self.stories.iter().any(|s| &s.id == id),
+                "Unknown story ID: {}",
+                id
+            );
+            anyhow::ensure!(seen.insert(id), "Story {} is listed twice", id);
+        }
+
+        let mut rest = std::mem::take(&mut self.stories);
+        rest.sort_by_key(|s| s.priority);
+        let mut ordered: Vec<Story> = first
+            .iter()
+            .filter_map(|id| {
+                let idx = rest.iter().position(|s| &s.id == id)?;
+                Some(rest.remove(idx))
+            })
+            .collect();
+        ordered.extend(rest);
+        for (idx, story) in ordered.iter_mut().enumerate() {
+            story.priority = idx as i32 + 1;
+        }
+        self.stories = ordered;
+        Ok(())
+    }
+
+    /// IDs of the stories matching `selection`. Fails on unknown IDs or an empty result.
+    pub fn select(&self, selection: &StorySelection) -> Result<HashSet<String>> {
+        let named = selection
+            .stories
+            .iter()
+            .chain(&selection.from)
+            .chain(&selection.exclude);
+        for id in named {
+            anyhow::ensure!(
+                self.stories.iter().any(|s| &s.id == id),
+                "Unknown story ID: {}",
+                id
+            );
+        }
+
+        let mut ordered: Vec<&Story> = self.stories.iter().collect();
+        ordered.sort_by_key(|s| s.priority);
+        if let Some(from) = &selection.from {
+            let start = ordered.iter().position(|s| &s.id == from).unwrap_or(0);
+            ordered.drain(..start);
+        }
+
+        let selected: HashSet<String> = ordered
+            .into_iter()
+            .filter(|s| selection.stories.is_empty() || selection.stories.contains(&s.id))
+            .filter(|s| {
+                selection.tags.is_empty() || s.tags.iter().any(|t| selection.tags.contains(t))
+            })
+            .filter(|s| !selection.exclude.contains(&s.id))
+            .filter(|s| !s.tags.iter().any(|t| selection.exclude_tags.contains(t)))
+            .map(|s| s.id.clone())
+            .collect();
+
+        anyhow::ensure!(!selected.is_empty(), "No stories match the selection");
+        Ok(selected)
+    }
+
+    /// Stories that cannot run because a dependency, directly or transitively, is blocked,
+    /// skipped or waiting for a human.
+    pub fn blocked_dependents(&self) -> Vec<&Story> {
+        let mut blocked: HashSet<&str> = self
+            .stories
+            .iter()
+            .filter(|s| s.status.is_stuck())
+            .map(|s| s.id.as_str())
+            .collect();
+        let mut dependents = Vec::new();
+
+        loop {
+            let found = dependents.len();
+            for story in &self.stories {
+                if story.passes() || blocked.contains(story.id.as_str()) {
+                    continue;
+                }
+                if story
+                    .depends_on
+                    .iter()
+                    .any(|d| blocked.contains(d.as_str()))
+                {
+                    blocked.insert(&story.id);
+                    dependents.push(story);
+                }
+            }
+            if dependents.len() == found {
+                break;
+            }
+        }
+
+        dependents
+    }
+}
+
+/// Returns the IDs along the first dependency cycle found, with the starting ID repeated at the end.
+fn find_dependency_cycle(prd: &Prd) -> Option<Vec<String>> {
+    fn visit<'a>(
+        id: &'a str,
+        deps: &HashMap<&'a str, &'a [String]>,
+        done: &mut HashSet<&'a str>,
+        path: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(pos) = path.iter().position(|p| *p == id) {
+            let mut cycle: Vec<String> = path[pos..].iter().map(|s| s.to_string()).collect();
+            cycle.push(id.to_string());
+            return Some(cycle);
+        }
+        if done.contains(id) {
+            return None;
+        }
+
+        path.push(id);
+        for dep in deps.get(id).copied().unwrap_or_default() {
+            if let Some(cycle) = visit(dep, deps, done, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(id);
+        None
     }
+
+    let deps: HashMap<&str, &[String]> = prd
+        .stories
+        .iter()
+        .map(|s| (s.id.as_str(), s.depends_on.as_slice()))
+        .collect();
+    let mut done = HashSet::new();
+
+    prd.stories
+        .iter()
+        .find_map(|s| visit(&s.id, &deps, &mut done, &mut Vec::new()))
 }
 
-pub fn load_prd(path: &PathBuf) -> Result<Prd> {
+pub fn load_prd(path: &Path) -> Result<Prd> {
     let content = fs::read_to_string(path).context("Failed to read prd.json")?;
     serde_json::from_str(&content).context("Failed to parse prd.json")
 }
 
-pub fn save_prd(path: &PathBuf, prd: &Prd) -> Result<()> {
+pub fn save_prd(path: &Path, prd: &Prd) -> Result<()> {
     let content = serde_json::to_string_pretty(prd)?;
     fs::write(path, content).context("Failed to write prd.json")
 }
@@ -83,12 +603,70 @@ pub fn validate_prd(prd: &Prd) -> Result<()> {
             "Duplicate story ID: {}",
             story.id
         );
+
+        anyhow::ensure!(
+            story.max_attempts != Some(0),
+            "Story {} has max_attempts of 0",
+            story.id
+        );
+        if let Some(timeout) = &story.timeout {
+            parse_duration(timeout)
+                .with_context(|| format!("Story {} has an invalid timeout", story.id))?;
+        }
+        anyhow::ensure!(
+            story
+                .tags
+                .iter()
+                .all(|t| !t.is_empty() && !t.contains(char::is_whitespace)),
+            "Story {} has an empty tag or one containing whitespace",
+            story.id
+        );
+        if let Some(scope) = &story.scope {
+            anyhow::ensure!(!scope.is_empty(), "Story {} has an empty scope", story.id);
+            anyhow::ensure!(
+                scope.iter().all(|g| !g.trim().is_empty()),
+                "Story {} has an empty scope pattern",
+                story.id
+            );
+        }
+        if let Some(policy) = &story.permissions {
+            policy
+                .validate()
+                .with_context(|| format!("Story {} has invalid permissions", story.id))?;
+        }
+
+        for step in story.verify.iter().flatten() {
+            anyhow::ensure!(
+                !step.command.trim().is_empty(),
+                "Story {} has an empty verification command for step '{}'",
+                story.id,
+                step.name
+            );
+        }
+    }
+
+    for story in &prd.stories {
+        for dep in &story.depends_on {
+            anyhow::ensure!(dep != &story.id, "Story {} depends on itself", story.id);
+            anyhow::ensure!(
+                seen_ids.contains(dep),
+                "Story {} depends on unknown story {}",
+                story.id,
+                dep
+            );
+        }
+    }
+
+    if let Some(cycle) = find_dependency_cycle(prd) {
+        anyhow::bail!("Dependency cycle detected: {}", cycle.join(" -> "));
     }
 
     Ok(())
 }
 
-pub fn display_prd_summary(prd: &Prd) {
+/// Prints the PRD's stories with their status, listed in file order or grouped under each
+/// tag (a story with several tags appears in each group).
+pub fn display_prd_summary(prd: &Prd, group_by_tag: bool) {
     if output::is_quiet() {
         return;
     }
@@ -99,51 +677,166 @@ pub fn display_prd_summary(prd: &Prd) {
         "Branch:".dimmed(),
         prd.branch_name.cyan()
     );
+    let counts: Vec<String> = StoryStatus::ALL
+        .iter()
+        .map(|status| {
+            (
+                status,
+                prd.stories.iter().filter(|s| s.status == *status).count(),
+            )
+        })
+        .filter(|(_, count)| *count > 0)
+        .map(|(status, count)| format!("{count} {status}"))
+        .collect();
     eprintln!(
-        "  {} {}\n",
+        "  {} {} {}\n",
         "Stories:".dimmed(),
-        prd.stories.len().to_string().cyan()
+        prd.stories.len().to_string().cyan(),
+        format!("({})", counts.join(", ")).dimmed()
     );
 
+    if group_by_tag {
+        let mut tags: Vec<&str> = prd
+            .stories
+            .iter()
+            .flat_map(|s| s.tags.iter().map(String::as_str))
+            .collect();
+        tags.sort();
+        tags.dedup();
+        let groups = tags
+            .into_iter()
+            .map(|tag| {
+                (
+                    tag,
+                    prd.stories
+                        .iter()
+                        .filter(|s| s.tags.iter().any(|t| t == tag))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .chain(std::iter::once((
+                "untagged",
+                prd.stories.iter().filter(|s| s.tags.is_empty()).collect(),
+            )))
+            .filter(|(_, stories)| !stories.is_empty());
+
+        for (tag, stories) in groups {
+            let passed = stories.iter().filter(|s| s.passes()).count();
+            eprintln!(
+                "  {} {}",
+                tag.bold().cyan(),
+                format!("({passed}/{} passed)", stories.len()).dimmed()
+            );
+            for story in stories {
+                print_story(story);
+            }
+            eprintln!();
+        }
+        return;
+    }
+
     for story in &prd.stories {
-        let status_icon = if story.passes {
-            "✓".green().bold()
-        } else {
-            "○".dimmed()
-        };
+        print_story(story);
+    }
+    eprintln!();
+}
 
-        let priority_badge = format!("P{}", story.priority);
-        let priority_colored = match story.priority {
-            1 => priority_badge.red().bold(),
-            2 => priority_badge.yellow(),
-            3 => priority_badge.blue(),
-            _ => priority_badge.dimmed(),
-        };
+/// One story's lines in the PRD summary.
+fn print_story(story: &Story) {
+    let status_icon = output::status_icon(story.status);
+
+    let priority_badge = format!("P{}", story.priority);
+    let priority_colored = match story.priority {
+        1 => priority_badge.red().bold(),
+        2 => priority_badge.yellow(),
+        3 => priority_badge.blue(),
+        _ => priority_badge.dimmed(),
+    };
 
-        let title = if story.passes {
-            story.title.green()
-        } else {
-            story.title.normal()
+    let title = match story.status {
+        StoryStatus::Passed => story.title.green(),
+        StoryStatus::Failed | StoryStatus::Blocked => story.title.red(),
+        StoryStatus::NeedsHuman => story.title.magenta(),
+        _ => story.title.normal(),
+    };
+
+    eprintln!(
+        "  {} {} [{}] {}",
+        status_icon,
+        story.id.bold(),
+        priority_colored,
+        title
+    );
+
+    if story.status != StoryStatus::Pending {
+        let since = match story.status {
+            StoryStatus::InProgress => story.started_at.as_deref(),
+            _ => story.finished_at.as_deref(),
+        };
+        let since = since
+            .and_then(local_time)
+            .map(|t| format!(" since {t}"))
+            .unwrap_or_default();
+        let attempts = match story.attempt_count {
+            0 => String::new(),
+            1 => ", 1 attempt".to_string(),
+            n => format!(", {n} attempts"),
         };
+        eprintln!(
+            "      {} {}{since}{attempts}",
+            "Status:".dimmed(),
+            story.status
+        );
+    }
 
+    if let Some(last) = story.attempts.last() {
+        let when = local_time(&last.finished_at)
+            .map(|t| format!(" at {t}"))
+            .unwrap_or_default();
+        let took = format_duration(Duration::from_millis(last.duration_ms));
+        let error = last
+            .error
+            .as_deref()
+            .map(|e| format!(": {}", e.lines().next().unwrap_or_default()))
+            .unwrap_or_default();
         eprintln!(
-            "  {} {} [{}] {}",
-            status_icon,
-            story.id.bold(),
-            priority_colored,
-            title
+            "      {} #{} {}{when} ({took}){error}",
+            "Last attempt:".dimmed(),
+            last.number,
+            last.outcome
         );
+    }
+
+    eprintln!(
+        "      {} {} items",
+        "Criteria:".dimmed(),
+        story.acceptance_criteria.len()
+    );
 
+    if !story.depends_on.is_empty() {
         eprintln!(
-            "      {} {} items",
-            "Criteria:".dimmed(),
-            story.acceptance_criteria.len()
+            "      {} {}",
+            "Depends on:".dimmed(),
+            story.depends_on.join(", ")
         );
     }
-    eprintln!();
+
+    if !story.tags.is_empty() {
+        eprintln!("      {} {}", "Tags:".dimmed(), story.tags.join(", "));
+    }
 }
 
-pub fn check_output_file(path: &PathBuf, force: bool) -> Result<()> {
+/// An RFC 3339 timestamp as local `YYYY-MM-DD HH:MM`.
+pub fn local_time(timestamp: &str) -> Option<String> {
+    let time = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some(
+        time.with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+    )
+}
+
+pub fn check_output_file(path: &Path, force: bool) -> Result<()> {
     if path.exists() && !force {
         anyhow::bail!(
             "PRD file already exists at {}. Use --force to overwrite.",
@@ -152,3 +845,459 @@ pub fn check_output_file(path: &PathBuf, force: bool) -> Result<()> {
     }
     Ok(())
 }
+
+/// Names of the fields that differ between two versions of a story.
+pub fn changed_fields(old: &Story, new: &Story) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old_fields)), Ok(serde_json::Value::Object(new_fields))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+
+    let mut fields: Vec<String> = old_fields
+        .keys()
+        .chain(new_fields.keys())
+        .filter(|key| old_fields.get(*key) != new_fields.get(*key))
+        .cloned()
+        .collect();
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+/// Story-level differences between two PRDs, for previews.
+#[derive(Debug, Default)]
+pub struct PrdDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Story IDs with the names of their changed fields
+    pub changed: Vec<(String, Vec<String>)>,
+}
+
+impl PrdDiff {
+    pub fn between(before: &Prd, after: &Prd) -> Self {
+        let mut diff = PrdDiff::default();
+        for new in &after.stories {
+            match before.stories.iter().find(|s| s.id == new.id) {
+                Some(old) => {
+                    let fields = changed_fields(old, new);
+                    if !fields.is_empty() {
+                        diff.changed.push((new.id.clone(), fields));
+                    }
+                }
+                None => diff.added.push(new.id.clone()),
+            }
+        }
+        diff.removed = before
+            .stories
+            .iter()
+            .filter(|old| !after.stories.iter().any(|s| s.id == old.id))
+            .map(|s| s.id.clone())
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The ID after the highest numbered one in the PRD, keeping its prefix and zero padding
+/// (`STORY-012` is followed by `STORY-013`).
+pub fn next_story_id(prd: &Prd) -> String {
+    let numbered = prd.stories.iter().filter_map(|s| {
+        let digits = s.id.len() - s.id.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        let (prefix, number) = s.id.split_at(s.id.len() - digits);
+        Some((prefix, digits, number.parse::<u64>().ok()?))
+    });
+
+    match numbered.max_by_key(|(_, _, number)| *number) {
+        Some((prefix, width, number)) => format!("{prefix}{:0width$}", number + 1),
+        None => format!("STORY-{:03}", prd.stories.len() + 1),
+    }
+}
+
+/// Result of merging a proposed PRD into an existing one.
+pub struct Amendment {
+    pub prd: Prd,
+    /// What ramph changed in the proposal to keep the existing PRD intact
+    pub notes: Vec<String>,
+}
+
+/// Story fields a planner sets; the rest (status, timestamps, attempts) is ramph's
+/// bookkeeping, which planners routinely leave out.
+const PLANNER_FIELDS: &[&str] = &[
+    "title",
+    "description",
+    "acceptance_criteria",
+    "priority",
+    "depends_on",
+    "tags",
+    "scope",
+];
+
+/// Merges `proposed` (the planner's full updated PRD) into `existing`. Passed stories stay
+/// exactly as they were, the status of other existing stories is carried over, and new
+/// stories are numbered after the existing ones.
+pub fn amend_prd(existing: &Prd, proposed: Prd) -> Amendment {
+    let mut notes = Vec::new();
+    let mut prd = Prd {
+        branch_name: existing.branch_name.clone(),
+        stories: Vec::new(),
+    };
+
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    let mut numbering = Prd {
+        branch_name: String::new(),
+        stories: existing.stories.clone(),
+    };
+    for mut story in proposed.stories {
+        match existing.stories.iter().find(|s| s.id == story.id) {
+            Some(old) if old.passes() => {
+                if changed_fields(old, &story)
+                    .iter()
+                    .any(|field| PLANNER_FIELDS.contains(&field.as_str()))
+                {
+                    notes.push(format!("kept passed story {} unchanged", old.id));
+                }
+                story = old.clone();
+            }
+            Some(old) => {
+                story.status = old.status;
+                story.started_at = old.started_at.clone();
+                story.finished_at = old.finished_at.clone();
+                story.attempt_count = old.attempt_count;
+                story.attempts = old.attempts.clone();
+            }
+            None => {
+                let id = next_story_id(&numbering);
+                if id != story.id {
+                    notes.push(format!("renumbered {} to {id}", story.id));
+                    renamed.insert(story.id.clone(), id.clone());
+                    story.id = id;
+                }
+                story.status = StoryStatus::Pending;
+                story.started_at = None;
+                story.finished_at = None;
+                story.attempt_count = 0;
+                story.attempts.clear();
+                numbering.stories.push(story.clone());
+            }
+        }
+        prd.stories.push(story);
+    }
+
+    for story in &mut prd.stories {
+        for dep in &mut story.depends_on {
+            if let Some(id) = renamed.get(dep) {
+                *dep = id.clone();
+            }
+        }
+    }
+
+    for old in existing.stories.iter().filter(|s| s.passes()) {
+        if !prd.stories.iter().any(|s| s.id == old.id) {
+            notes.push(format!("restored passed story {}", old.id));
+            prd.stories.push(old.clone());
+        }
+    }
+
+    Amendment { prd, notes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story(id: &str, priority: i32, depends_on: &[&str]) -> Story {
+        Story {
+            id: id.to_string(),
+            title: format!("Story {id}"),
+            description: "Do the thing".to_string(),
+            priority,
+            acceptance_criteria: vec!["It works".to_string()],
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn prd(stories: Vec<Story>) -> Prd {
+        Prd {
+            branch_name: "feature/test".to_string(),
+            stories,
+        }
+    }
+
+    fn ids(stories: &[Story]) -> Vec<&str> {
+        stories.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    fn selected(prd: &Prd, selection: &StorySelection) -> Vec<String> {
+        let mut ids: Vec<String> = prd.select(selection).unwrap().into_iter().collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn next_story_id_keeps_prefix_and_padding() {
+        let numbered = prd(vec![story("STORY-009", 1, &[]), story("STORY-012", 1, &[])]);
+        assert_eq!(next_story_id(&numbered), "STORY-013");
+
+        let short = prd(vec![story("US-9", 1, &[])]);
+        assert_eq!(next_story_id(&short), "US-10");
+
+        let unnumbered = prd(vec![story("login", 1, &[]), story("logout", 1, &[])]);
+        assert_eq!(next_story_id(&unnumbered), "STORY-003");
+    }
+
+    #[test]
+    fn finds_dependency_cycles() {
+        let acyclic = prd(vec![
+            story("A", 1, &[]),
+            story("B", 1, &["A"]),
+            story("C", 1, &["A", "B"]),
+        ]);
+        assert!(find_dependency_cycle(&acyclic).is_none());
+        assert!(validate_prd(&acyclic).is_ok());
+
+        let cyclic = prd(vec![
+            story("A", 1, &["C"]),
+            story("B", 1, &["A"]),
+            story("C", 1, &["B"]),
+        ]);
+        let cycle = find_dependency_cycle(&cyclic).unwrap();
+        assert_eq!(cycle.first(), cycle.last());
+        assert_eq!(cycle.len(), 4);
+        assert!(validate_prd(&cyclic).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unknown_dependencies() {
+        assert!(validate_prd(&prd(vec![story("A", 1, &["Z"])])).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_empty_scope() {
+        let mut scoped = story("A", 1, &[]);
+        scoped.scope = Some(Vec::new());
+        assert!(validate_prd(&prd(vec![scoped])).is_err());
+    }
+
+    #[test]
+    fn select_applies_filters() {
+        let mut stories = vec![
+            story("A", 1, &[]),
+            story("B", 2, &[]),
+            story("C", 3, &[]),
+            story("D", 4, &[]),
+        ];
+        stories[0].tags = vec!["api".to_string()];
+        stories[2].tags = vec!["api".to_string(), "slow".to_string()];
+        let prd = prd(stories);
+
+        let tagged = StorySelection {
+            tags: vec!["api".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(selected(&prd, &tagged), ["A", "C"]);
+
+        let from = StorySelection {
+            from: Some("B".to_string()),
+            exclude: vec!["D".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(selected(&prd, &from), ["B", "C"]);
+
+        let unknown = StorySelection {
+            stories: vec!["Z".to_string()],
+            ..Default::default()
+        };
+        assert!(prd.select(&unknown).is_err());
+
+        let empty = StorySelection {
+            tags: vec!["nothing".to_string()],
+            ..Default::default()
+        };
+        assert!(prd.select(&empty).is_err());
+    }
+
+    #[test]
+    fn select_excludes_tags() {
+        let mut stories = vec![story("A", 1, &[]), story("B", 2, &[]), story("C", 3, &[])];
+        stories[0].tags = vec!["api".to_string()];
+        stories[2].tags = vec!["api".to_string(), "slow".to_string()];
+        let prd = prd(stories);
+
+        let not_slow = StorySelection {
+            tags: vec!["api".to_string()],
+            exclude_tags: vec!["slow".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(selected(&prd, &not_slow), ["A"]);
+
+        let untagged_only = StorySelection {
+            exclude_tags: vec!["api".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(selected(&prd, &untagged_only), ["B"]);
+    }
+
+    #[test]
+    fn renumber_moves_named_stories_first() {
+        let mut prd = prd(vec![
+            story("A", 2, &[]),
+            story("B", 1, &[]),
+            story("C", 5, &[]),
+            story("D", 2, &[]),
+        ]);
+        prd.renumber(&["C".to_string()]).unwrap();
+        assert_eq!(ids(&prd.stories), ["C", "B", "A", "D"]);
+        let priorities: Vec<i32> = prd.stories.iter().map(|s| s.priority).collect();
+        assert_eq!(priorities, [1, 2, 3, 4]);
+
+        assert!(prd.renumber(&["Z".to_string()]).is_err());
+        assert!(prd.renumber(&["A".to_string(), "A".to_string()]).is_err());
+        assert_eq!(ids(&prd.stories), ["C", "B", "A", "D"]);
+    }
+
+    #[test]
+    fn reset_keeps_attempt_numbers_increasing() {
+        let mut story = story("A", 1, &[]);
+        story.set_status(StoryStatus::Blocked);
+        story.attempt_count = 2;
+        for number in 1..=2 {
+            story.attempts.push(Attempt {
+                number,
+                started_at: String::new(),
+                finished_at: String::new(),
+                outcome: AttemptOutcome::Failed,
+                error: None,
+                session_id: None,
+                commit: None,
+                num_turns: None,
+                duration_ms: 0,
+            });
+        }
+        let mut prd = prd(vec![story]);
+
+        let reset = prd.reset_stories(&HashSet::from(["A".to_string()]));
+        assert_eq!(reset, ["A"]);
+        let story = &prd.stories[0];
+        assert_eq!(story.status, StoryStatus::Pending);
+        assert_eq!(story.attempt_count, 0);
+        assert_eq!(story.next_attempt_number(), 3);
+    }
+
+    #[test]
+    fn legacy_flags_map_to_status() {
+        let json = r#"{
+            "branchName": "feature/old",
+            "stories": [
+                {"id": "A", "title": "A", "description": "d", "passes": true},
+                {"id": "B", "title": "B", "description": "d", "blocked": true},
+                {"id": "C", "title": "C", "description": "d", "passes": false},
+                {"id": "D", "title": "D", "description": "d", "status": "failed", "passes": true},
+                {"id": "E", "title": "E", "description": "d", "status": "needs_human"}
+            ]
+        }"#;
+        let prd: Prd = serde_json::from_str(json).unwrap();
+        let statuses: Vec<StoryStatus> = prd.stories.iter().map(|s| s.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                StoryStatus::Passed,
+                StoryStatus::Blocked,
+                StoryStatus::Pending,
+                StoryStatus::Passed,
+                StoryStatus::NeedsHuman,
+            ]
+        );
+
+        let saved = serde_json::to_value(&prd).unwrap();
+        assert!(saved["stories"][0].get("passes").is_none());
+        assert_eq!(saved["stories"][0]["status"], "passed");
+    }
+
+    fn amend_base() -> Prd {
+        let mut done = story("STORY-001", 1, &[]);
+        done.set_status(StoryStatus::Passed);
+        done.attempt_count = 1;
+        let mut failing = story("STORY-002", 2, &[]);
+        failing.set_status(StoryStatus::Failed);
+        failing.attempt_count = 2;
+        prd(vec![done, failing])
+    }
+
+    #[test]
+    fn amend_keeps_passed_stories_and_carries_status_over() {
+        let existing = amend_base();
+        let mut rewritten = story("STORY-001", 1, &[]);
+        rewritten.title = "Rewritten".to_string();
+        let mut revised = story("STORY-002", 2, &[]);
+        revised.title = "Revised".to_string();
+
+        let amended = amend_prd(&existing, prd(vec![rewritten, revised]));
+        let stories = &amended.prd.stories;
+        assert_eq!(stories[0].title, "Story STORY-001");
+        assert_eq!(stories[0].status, StoryStatus::Passed);
+        assert_eq!(stories[1].title, "Revised");
+        assert_eq!(stories[1].status, StoryStatus::Failed);
+        assert_eq!(stories[1].attempt_count, 2);
+        assert_eq!(amended.notes, ["kept passed story STORY-001 unchanged"]);
+    }
+
+    #[test]
+    fn amend_renumbers_new_stories_and_restores_dropped_passed_ones() {
+        let existing = amend_base();
+        let mut first = story("NEW-1", 3, &["STORY-001"]);
+        first.set_status(StoryStatus::Passed);
+        let second = story("NEW-2", 4, &["NEW-1"]);
+
+        let amended = amend_prd(
+            &existing,
+            prd(vec![story("STORY-002", 2, &[]), first, second]),
+        );
+        let stories = &amended.prd.stories;
+        assert_eq!(
+            ids(stories),
+            ["STORY-002", "STORY-003", "STORY-004", "STORY-001"]
+        );
+        assert_eq!(stories[1].status, StoryStatus::Pending);
+        assert_eq!(stories[2].depends_on, ["STORY-003"]);
+        assert!(
+            amended
+                .notes
+                .contains(&"renumbered NEW-1 to STORY-003".to_string())
+        );
+        assert!(
+            amended
+                .notes
+                .contains(&"restored passed story STORY-001".to_string())
+        );
+        assert!(validate_prd(&amended.prd).is_ok());
+    }
+
+    #[test]
+    fn amend_ignores_bookkeeping_missing_from_a_copied_passed_story() {
+        let mut existing = amend_base();
+        existing.stories[0].started_at = Some("2026-01-01T10:00:00+00:00".to_string());
+        existing.stories[0].attempts = vec![Attempt {
+            number: 1,
+            started_at: "2026-01-01T10:00:00+00:00".to_string(),
+            finished_at: "2026-01-01T10:20:00+00:00".to_string(),
+            outcome: AttemptOutcome::Passed,
+            error: None,
+            session_id: None,
+            commit: None,
+            num_turns: None,
+            duration_ms: 1_200_000,
+        }];
+        // The planner copies the story's content but not ramph's bookkeeping
+        let copied = story("STORY-001", 1, &[]);
+
+        let amended = amend_prd(&existing, prd(vec![copied, story("STORY-002", 2, &[])]));
+        assert!(amended.notes.is_empty(), "{:?}", amended.notes);
+        assert_eq!(amended.prd.stories[0].attempts.len(), 1);
+        assert_eq!(amended.prd.stories[0].status, StoryStatus::Passed);
+    }
+}
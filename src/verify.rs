@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::output;
+use crate::types::VerifyStep;
+
+/// How much of a failing step's output is kept for the progress log.
+const OUTPUT_TAIL_CHARS: usize = 4000;
+
+#[derive(Debug)]
+pub struct StepResult {
+    pub name: String,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub output: String,
+}
+
+impl StepResult {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs each step in order through `sh -c`, stopping at the first failure.
+pub async fn run_verification(steps: &[VerifyStep], cwd: &Path) -> Result<Vec<StepResult>> {
+    let mut results = Vec::new();
+
+    for step in steps {
+        output::verbose(&format!("verify {}: {}", step.name, step.command));
+
+        let out = Command::new("sh")
+            .arg("-c")
+            .arg(&step.command)
+            .current_dir(cwd)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .with_context(|| format!("Failed to spawn verification step '{}'", step.name))?;
+
+        let mut combined = String::from_utf8_lossy(&out.stdout).to_string();
+        combined.push_str(&String::from_utf8_lossy(&out.stderr));
+
+        let result = StepResult {
+            name: step.name.clone(),
+            command: step.command.clone(),
+            exit_code: out.status.code(),
+            output: combined,
+        };
+        let failed = !result.success();
+        results.push(result);
+
+        if failed {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn all_passed(results: &[StepResult]) -> bool {
+    results.iter().all(StepResult::success)
+}
+
+/// Formats the first failing step as a progress-log entry body.
+pub fn failure_report(results: &[StepResult]) -> String {
+    let Some(failed) = results.iter().find(|r| !r.success()) else {
+        return String::new();
+    };
+
+    let exit = match failed.exit_code {
+        Some(code) => format!("exited with {code}"),
+        None => "was killed by a signal".to_string(),
+    };
+
+    let trimmed = failed.output.trim();
+    let tail_start = trimmed
+        .char_indices()
+        .rev()
+        .nth(OUTPUT_TAIL_CHARS - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+
+    format!(
+        "Step `{}` (`{}`) {}:\n```\n{}\n```",
+        failed.name,
+        failed.command,
+        exit,
+        &trimmed[tail_start..]
+    )
+}
@@ -1,21 +1,348 @@
 use anyhow::{Context, Result};
-use chrono::Local;
+use chrono::{DateTime, Local};
 use colored::Colorize;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::Instant;
 
-use crate::amp::run_iteration;
+use crate::backend::{
+    self, AgentBackend, AgentEvent, IterationOptions, SessionInfo, render_event, run_iteration,
+};
+use crate::codebase::{PlanContextConfig, collect_snapshot};
+use crate::config::{
+    Config, DEFAULT_MAX_ATTEMPTS, FailurePolicy, ScopePolicy, format_duration, load_config,
+    parse_duration, ramph_dir,
+};
+use crate::conversation::{Conversation, Speaker, new_conversation_path};
+use crate::git;
 use crate::output;
+use crate::permissions::PermissionPolicy;
 use crate::prompts::*;
+use crate::signal::Interrupt;
+use crate::snapshot::{IterationChange, IterationClassification, Snapshot};
+use crate::state::{self, RunState, RunStatus};
+use crate::transcript::{TranscriptEntry, find_transcripts, read_transcript, transcript_path};
 use crate::types::*;
+use crate::verify;
+use crate::worktree::StoryWorktree;
 
-pub async fn run_command(
+pub struct RunOptions {
+    pub cwd: PathBuf,
+    pub prd: PathBuf,
+    pub progress: Option<PathBuf>,
+    pub prompt: Option<PathBuf>,
+    pub config: PathBuf,
+    pub max_iterations: usize,
+    pub max_attempts: Option<u32>,
+    pub jobs: usize,
+    pub base: Option<String>,
+    pub allow_dirty: bool,
+    pub resume: bool,
+    pub iteration_timeout: Option<Duration>,
+    pub deadline: Option<DateTime<Local>>,
+    pub on_failure: Option<FailurePolicy>,
+    pub scope_policy: Option<ScopePolicy>,
+    pub dry_run: bool,
+    pub prompts_dir: Option<PathBuf>,
+    pub selection: StorySelection,
+    pub force: bool,
+    /// Run even if the backend cannot enforce the permission policy (it is then ignored)
+    pub allow_unenforced_permissions: bool,
+    /// End with the PRD summary grouped by tag
+    pub group_by_tag: bool,
+}
+
+/// Settings resolved from the command line, config file and defaults.
+struct RunSettings {
+    agent: Box<dyn AgentBackend>,
+    base_prompt: String,
+    config: Config,
+    default_max_attempts: u32,
+    iteration_timeout: Option<Duration>,
+    on_failure: FailurePolicy,
+    scope_policy: ScopePolicy,
+    /// IDs of the stories this run may pick
+    selected: HashSet<String>,
+}
+
+/// Run-wide state shared by every attempt.
+struct RunContext {
     cwd: PathBuf,
-    prd: PathBuf,
-    progress: Option<PathBuf>,
-    prompt: Option<PathBuf>,
-    max_iterations: usize,
-) -> Result<()> {
+    prd_path: PathBuf,
+    progress_path: PathBuf,
+    settings: RunSettings,
+    /// No new attempts start after this time
+    deadline: Option<DateTime<Local>>,
+    interrupt: Interrupt,
+    state: RefCell<RunState>,
+    /// Agent sessions of attempts cut short by an interrupt, continued on `--resume`
+    resume_sessions: RefCell<HashMap<String, String>>,
+}
+
+/// A story selected for an attempt, detached from the PRD it was loaded from.
+struct AttemptPlan {
+    story: Story,
+    /// Which try this is against the story's `max_attempts` budget
+    attempt: u32,
+    max_attempts: u32,
+    /// Number of the attempt in the story's history (see `Story::next_attempt_number`)
+    number: u32,
+    verify_steps: Vec<VerifyStep>,
+    timeout: Option<Duration>,
+    continue_session: Option<String>,
+    permissions: PermissionPolicy,
+}
+
+/// What is known about an attempt so far; filled in as it runs, so it survives cancellation.
+#[derive(Default)]
+struct AttemptTrace {
+    /// RFC 3339 time the attempt started
+    started_at: String,
+    duration: Duration,
+    session: SessionInfo,
+    iteration: Option<IterationClassification>,
+    /// What the `--on-failure` policy did, for failed attempts
+    rollback: Option<String>,
+    /// Files changed outside the story's scope and what the `--scope-policy` did about them
+    scope: Option<String>,
+    /// How ramph repaired the PRD after the agent edited it out of bounds
+    prd_repair: Option<String>,
+    /// Where the agent's events were saved, relative to the run directory
+    transcript: Option<PathBuf>,
+}
+
+impl AttemptTrace {
+    /// Lines describing the attempt for its progress log entry.
+    fn details(&self) -> String {
+        let mut details = String::new();
+        if let Some(iteration) = &self.iteration {
+            details.push_str(&format!("Result: {}\n", iteration.describe()));
+        }
+        if let Some(repair) = &self.prd_repair {
+            details.push_str(&format!("PRD guard: {repair}\n"));
+        }
+        if let Some(scope) = &self.scope {
+            details.push_str(&format!("Scope: {scope}\n"));
+        }
+        if let Some(rollback) = &self.rollback {
+            details.push_str(&format!("Rollback: {rollback}\n"));
+        }
+        if let Some(transcript) = &self.transcript {
+            details.push_str(&format!("Transcript: {}\n", transcript.display()));
+        }
+        details
+    }
+}
+
+struct AttemptReport {
+    outcome: AttemptOutcome,
+    /// Why a failed or timed out attempt did not pass
+    reason: Option<String>,
+    trace: AttemptTrace,
+}
+
+impl AttemptReport {
+    /// A failed attempt that never got to run the agent.
+    fn failed(reason: String) -> Self {
+        Self {
+            outcome: AttemptOutcome::Failed,
+            reason: Some(reason),
+            trace: AttemptTrace::default(),
+        }
+    }
+
+    /// The entry for this attempt in the story's `attempts` history.
+    fn to_attempt(&self, number: u32) -> Attempt {
+        let now = Local::now().to_rfc3339();
+        Attempt {
+            number,
+            started_at: if self.trace.started_at.is_empty() {
+                now.clone()
+            } else {
+                self.trace.started_at.clone()
+            },
+            finished_at: now,
+            outcome: self.outcome,
+            error: self.reason.as_deref().map(shorten),
+            session_id: self.trace.session.id.clone(),
+            commit: self
+                .trace
+                .iteration
+                .as_ref()
+                .filter(|iteration| iteration.commits > 0)
+                .map(|iteration| iteration.head.clone()),
+            num_turns: self.trace.session.num_turns,
+            duration_ms: self.trace.duration.as_millis() as u64,
+        }
+    }
+}
+
+/// The first few lines of a failure reason, short enough to keep in the PRD.
+fn shorten(reason: &str) -> String {
+    const MAX_CHARS: usize = 300;
+    let lines: Vec<&str> = reason
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(3)
+        .collect();
+    let short = lines.join("\n");
+    if short.chars().count() > MAX_CHARS {
+        let cut: String = short.chars().take(MAX_CHARS).collect();
+        format!("{cut}...")
+    } else {
+        short
+    }
+}
+
+/// Where an attempt runs: the main checkout or a story worktree.
+struct Workspace {
+    dir: PathBuf,
+    prd_path: PathBuf,
+    progress_path: PathBuf,
+}
+
+impl RunSettings {
+    fn plan_attempt(&self, story: &Story) -> AttemptPlan {
+        AttemptPlan {
+            story: story.clone(),
+            attempt: story.attempt_count + 1,
+            max_attempts: story.max_attempts.unwrap_or(self.default_max_attempts),
+            number: story.next_attempt_number(),
+            verify_steps: story
+                .verify
+                .clone()
+                .unwrap_or_else(|| self.config.verify.clone()),
+            timeout: story
+                .timeout
+                .as_deref()
+                .and_then(|t| parse_duration(t).ok())
+                .or(self.iteration_timeout),
+            continue_session: None,
+            permissions: match &story.permissions {
+                Some(overrides) => self.config.permissions.with_overrides(overrides),
+                None => self.config.permissions.clone(),
+            },
+        }
+    }
+
+    /// Ready stories within the selection, in scheduling order.
+    fn ready_stories<'a>(&self, prd: &'a Prd) -> Vec<&'a Story> {
+        prd.ready_stories()
+            .into_iter()
+            .filter(|s| self.selected.contains(&s.id))
+            .collect()
+    }
+
+    fn completed(&self, prd: &Prd) -> u64 {
+        prd.stories
+            .iter()
+            .filter(|s| s.passes() && self.selected.contains(&s.id))
+            .count() as u64
+    }
+
+    fn report_queue_exhausted(&self, prd: &Prd) {
+        let remaining = prd.stories.iter().any(|s| {
+            !matches!(s.status, StoryStatus::Passed | StoryStatus::Skipped)
+                && self.selected.contains(&s.id)
+        });
+        let subset = self.selected.len() < prd.stories.len();
+        if !remaining && subset {
+            output::success("All selected stories complete!");
+        } else if !remaining {
+            output::success("All stories complete!");
+        } else if subset {
+            output::warn(
+                "No eligible stories left; remaining selected stories are blocked, need a human, or wait on stories outside the selection",
+            );
+        } else {
+            output::warn("No eligible stories left; remaining stories are blocked or need a human");
+        }
+    }
+}
+
+impl RunContext {
+    fn plan_attempt(&self, story: &Story) -> AttemptPlan {
+        AttemptPlan {
+            continue_session: self.resume_sessions.borrow_mut().remove(&story.id),
+            ..self.settings.plan_attempt(story)
+        }
+    }
+
+    fn deadline_passed(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Local::now() >= deadline)
+    }
+
+    fn report_deadline(&self) -> Result<()> {
+        output::warn("Run deadline reached; not starting any more stories");
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        append_progress(
+            &self.progress_path,
+            &format!("\n## [{timestamp}] Deadline reached; run stopped\n"),
+        )
+    }
+
+    fn save_state(&self) -> Result<()> {
+        state::save_state(&self.cwd, &mut self.state.borrow_mut())
+    }
+
+    /// Marks an attempt as in flight in the run state and the PRD.
+    fn begin_attempt(&self, story_id: &str) -> Result<()> {
+        {
+            let mut state = self.state.borrow_mut();
+            state.iteration += 1;
+            state.current_stories.push(story_id.to_string());
+        }
+        self.save_state()?;
+
+        let mut prd = load_prd(&self.prd_path)?;
+        if let Some(story) = prd.stories.iter_mut().find(|s| s.id == story_id) {
+            story.set_status(StoryStatus::InProgress);
+        }
+        save_prd(&self.prd_path, &prd)
+    }
+}
+
+pub async fn run_command(opts: RunOptions) -> Result<()> {
+    run(opts, None).await
+}
+
+/// `run_command`, stopped by `interrupt` instead of Ctrl-C if one is given.
+async fn run(opts: RunOptions, interrupt: Option<Interrupt>) -> Result<()> {
+    let RunOptions {
+        cwd,
+        prd,
+        progress,
+        prompt,
+        config,
+        max_iterations,
+        max_attempts,
+        jobs,
+        base,
+        allow_dirty,
+        resume,
+        iteration_timeout,
+        deadline,
+        on_failure,
+        scope_policy,
+        dry_run,
+        prompts_dir,
+        selection,
+        force,
+        allow_unenforced_permissions,
+        group_by_tag,
+    } = opts;
+
+    anyhow::ensure!(jobs > 0, "--jobs must be at least 1");
+
     let prd_path = cwd.join(&prd);
     let progress_path = match &progress {
         Some(p) => cwd.join(p),
@@ -27,164 +354,2856 @@ pub async fn run_command(
         None => load_prompt(None)?,
     };
 
-    let initial_prd = load_prd(&prd_path)?;
-    let total_stories = initial_prd.stories.len() as u64;
-    let completed_initial = initial_prd.stories.iter().filter(|s| s.passes).count() as u64;
+    let config = load_config(&cwd.join(&config))?;
+    let default_max_attempts = max_attempts
+        .or(config.max_attempts)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    let iteration_timeout = match (iteration_timeout, &config.iteration_timeout) {
+        (Some(timeout), _) => Some(timeout),
+        (None, Some(timeout)) => Some(parse_duration(timeout)?),
+        (None, None) => None,
+    };
+    let on_failure = on_failure.or(config.on_failure).unwrap_or_default();
+    let scope_policy = scope_policy.or(config.scope_policy).unwrap_or_default();
+    let agent = backend::from_config(&config.agent, &cwd)?;
+
+    let mut initial_prd = load_prd(&prd_path)?;
+    validate_prd(&initial_prd).context("PRD validation failed")?;
+    let selected = initial_prd.select(&selection)?;
+
+    let settings = RunSettings {
+        agent,
+        base_prompt,
+        config,
+        default_max_attempts,
+        iteration_timeout,
+        on_failure,
+        scope_policy,
+        selected,
+    };
+
+    if dry_run {
+        if force {
+            initial_prd.reset_stories(&settings.selected);
+        }
+        return print_dry_run(
+            &settings,
+            &initial_prd,
+            &progress_path,
+            deadline,
+            prompts_dir.map(|dir| cwd.join(dir)).as_deref(),
+        );
+    }
 
-    output::header(&format!("=== ramph run ==="));
+    output::header("=== ramph run ===");
     output::info(&format!(
         "PRD: {} ({} stories, {} completed)",
         prd_path.display(),
-        total_stories,
-        completed_initial
+        initial_prd.stories.len(),
+        initial_prd.stories.iter().filter(|s| s.passes()).count()
     ));
+    output::info(&format!("Agent: {}", settings.agent.name()));
+    if !settings.config.permissions.is_unrestricted() {
+        output::info(&format!(
+            "Tool permissions: {}",
+            settings.config.permissions.describe()
+        ));
+    }
+    if restricts_permissions(&settings, &initial_prd) {
+        check_permissions_enforced(
+            settings.agent.as_ref(),
+            "the tool permissions policy",
+            allow_unenforced_permissions,
+        )?;
+    }
+    if !selection.is_empty() {
+        output::info(&format!(
+            "Selected {} of {} stories: {}",
+            settings.selected.len(),
+            initial_prd.stories.len(),
+            selected_ids(&initial_prd, &settings.selected).join(", ")
+        ));
+    }
+
+    let previous = state::load_state(&cwd)?;
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+
+    let mut run_state = if resume {
+        let Some(previous) = previous else {
+            anyhow::bail!(
+                "No previous run to resume (missing {})",
+                state::state_path(&cwd).display()
+            );
+        };
+        anyhow::ensure!(
+            previous.status != RunStatus::Finished,
+            "Run {} already finished; nothing to resume",
+            previous.run_id
+        );
+        anyhow::ensure!(
+            previous.branch == initial_prd.branch_name,
+            "Run {} was on branch {}, but the PRD now targets {}",
+            previous.run_id,
+            previous.branch,
+            initial_prd.branch_name
+        );
+
+        // The interrupted attempt's edits are expected to still be in the tree.
+        prepare_branch(
+            &cwd,
+            &initial_prd.branch_name,
+            base.as_deref(),
+            true,
+            &[&prd_path, &progress_path],
+        )?;
+        output::info(&format!(
+            "Resuming run {} after iteration {}",
+            previous.run_id, previous.iteration
+        ));
+        append_progress(
+            &progress_path,
+            &format!(
+                "\n## [{timestamp}] Run {} resumed on {}\n",
+                previous.run_id, previous.branch
+            ),
+        )?;
+        previous
+    } else {
+        if let Some(previous) = previous.filter(|p| p.status == RunStatus::Interrupted) {
+            output::warn(&format!(
+                "Run {} was interrupted; starting a new run (use --resume to continue it)",
+                previous.run_id
+            ));
+        }
+
+        let start_commit = prepare_branch(
+            &cwd,
+            &initial_prd.branch_name,
+            base.as_deref(),
+            allow_dirty,
+            &[&prd_path, &progress_path],
+        )?;
+        append_progress(
+            &progress_path,
+            &format!(
+                "\n## [{timestamp}] Run started on {} at {}\n",
+                initial_prd.branch_name,
+                git::short_sha(&start_commit)
+            ),
+        )?;
+        RunState::new(&initial_prd.branch_name, &start_commit)
+    };
+
+    // Parallel attempts run in throwaway worktrees, so there is nothing to continue.
+    let resume_sessions: HashMap<String, String> = if jobs == 1 {
+        run_state
+            .current_stories
+            .iter()
+            .filter_map(|id| Some((id.clone(), run_state.last_session(id)?.to_string())))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    run_state.current_stories.clear();
+    run_state.status = RunStatus::Running;
+
+    if force {
+        let reset = initial_prd.reset_stories(&settings.selected);
+        if !reset.is_empty() {
+            save_prd(&prd_path, &initial_prd)?;
+            output::info(&format!("Re-running: {}", reset.join(", ")));
+            append_progress(
+                &progress_path,
+                &format!("\n## [{timestamp}] Forced re-run: {}\n", reset.join(", ")),
+            )?;
+        }
+    } else if settings.completed(&initial_prd) == settings.selected.len() as u64 {
+        output::warn("Every selected story already passes (use --force to run them again)");
+    }
+
+    let start_commit = run_state.start_commit.clone();
+    let ctx = RunContext {
+        cwd,
+        prd_path,
+        progress_path,
+        settings,
+        deadline,
+        interrupt: interrupt.unwrap_or_else(Interrupt::install),
+        state: RefCell::new(run_state),
+        resume_sessions: RefCell::new(resume_sessions),
+    };
+    ctx.save_state()?;
+
+    if let Some(deadline) = ctx.deadline {
+        output::info(&format!(
+            "No new stories will start after {}",
+            deadline.format("%Y-%m-%d %H:%M")
+        ));
+    }
+
+    let progress_bar = output::create_progress_bar(ctx.settings.selected.len() as u64);
+    progress_bar.set_position(ctx.settings.completed(&initial_prd));
+
+    if jobs == 1 {
+        run_sequential(&ctx, max_iterations, &progress_bar).await?;
+    } else {
+        output::info(&format!(
+            "Running up to {jobs} stories in parallel worktrees"
+        ));
+        run_parallel(
+            &ctx,
+            &initial_prd.branch_name,
+            jobs,
+            max_iterations,
+            &progress_bar,
+        )
+        .await?;
+    }
+
+    progress_bar.finish_and_clear();
+
+    let interrupted = ctx.interrupt.is_set();
+    ctx.state.borrow_mut().status = if interrupted {
+        RunStatus::Interrupted
+    } else {
+        RunStatus::Finished
+    };
+    ctx.save_state()?;
 
-    let progress_bar = output::create_progress_bar(total_stories);
-    progress_bar.set_position(completed_initial);
+    print_final_summary(&ctx.prd_path, &ctx.cwd, &start_commit, group_by_tag)?;
+    if interrupted {
+        output::info("Run interrupted; continue it with `ramph run --resume`");
+    }
+
+    Ok(())
+}
+
+async fn run_sequential(
+    ctx: &RunContext,
+    max_iterations: usize,
+    progress_bar: &ProgressBar,
+) -> Result<()> {
+    let first = ctx.state.borrow().iteration + 1;
+
+    for iteration in first..=max_iterations {
+        if ctx.interrupt.is_set() {
+            break;
+        }
+        if ctx.deadline_passed() {
+            ctx.report_deadline()?;
+            break;
+        }
 
-    for iteration in 1..=max_iterations {
-        let prd = load_prd(&prd_path)?;
+        let prd = load_prd(&ctx.prd_path)?;
 
-        let Some(story) = prd.get_next_story() else {
+        let Some(story) = ctx.settings.ready_stories(&prd).into_iter().next() else {
             progress_bar.finish_and_clear();
-            output::success("All stories complete!");
+            ctx.settings.report_queue_exhausted(&prd);
             break;
         };
 
-        let story_id = story.id.clone();
-        let story_title = story.title.clone();
+        let plan = ctx.plan_attempt(story);
 
         output::header(&format!(
             "=== Iteration {}/{} ===",
             iteration, max_iterations
         ));
-        output::story_status(&story_id, &story_title, output::StoryStatus::Running);
+        output::story_status(&plan.story.id, &plan.story.title, StoryStatus::InProgress);
+        output::info(&format!("Attempt {}/{}", plan.attempt, plan.max_attempts));
+        if let Some(timeout) = plan.timeout {
+            output::verbose(&format!("iteration timeout: {}", format_duration(timeout)));
+        }
+        if let Some(session) = &plan.continue_session {
+            output::info(&format!("Continuing agent session {session}"));
+        }
 
-        let progress = load_progress(&progress_path)?;
-        let prompt = build_iteration_prompt(&base_prompt, story, &progress);
+        let spinner = output::create_spinner(&format!("Working on {}...", plan.story.id));
 
-        let spinner = output::create_spinner(&format!("Working on {}...", story_id));
+        ctx.begin_attempt(&plan.story.id)?;
+        let workspace = Workspace {
+            dir: ctx.cwd.clone(),
+            prd_path: ctx.prd_path.clone(),
+            progress_path: ctx.progress_path.clone(),
+        };
+        let report = run_attempt(ctx, &plan, &workspace, &spinner, true).await?;
+        let prd = record_attempt(ctx, &plan, &report, &spinner)?;
 
-        match run_iteration(&prompt, &cwd, Some(&spinner)).await {
-            Ok(_output) => {
-                output::finish_spinner_success(
-                    &spinner,
-                    &format!("Completed: {} - {}", story_id, story_title),
-                );
+        progress_bar.set_position(ctx.settings.completed(&prd));
+    }
+
+    Ok(())
+}
+
+/// Runs independent stories concurrently, each in its own worktree and agent session,
+/// merging successful attempts back into the feature branch as they finish.
+async fn run_parallel(
+    ctx: &RunContext,
+    branch: &str,
+    jobs: usize,
+    max_iterations: usize,
+    progress_bar: &ProgressBar,
+) -> Result<()> {
+    let prd_rel = ctx
+        .prd_path
+        .strip_prefix(&ctx.cwd)
+        .context("--jobs requires the PRD file to be inside --cwd")?;
+    let progress_rel = ctx
+        .progress_path
+        .strip_prefix(&ctx.cwd)
+        .context("--jobs requires the progress file to be inside --cwd")?;
+    let worktrees_dir = ramph_dir(&ctx.cwd)?.join("worktrees");
+
+    let multi = output::create_multi_progress();
+    let progress_bar = multi.add(progress_bar.clone());
+
+    let mut running = FuturesUnordered::new();
+    let mut active: HashSet<String> = HashSet::new();
+    let mut started = ctx.state.borrow().iteration;
+
+    loop {
+        let mut prd = load_prd(&ctx.prd_path)?;
 
-                let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-                append_progress(
-                    &progress_path,
-                    &format!("\n## [{timestamp}] Completed: {story_id}\n"),
-                )?;
+        while running.len() < jobs
+            && started < max_iterations
+            && !ctx.interrupt.is_set()
+            && !ctx.deadline_passed()
+        {
+            let Some(story) = ctx
+                .settings
+                .ready_stories(&prd)
+                .into_iter()
+                .find(|s| !active.contains(&s.id))
+            else {
+                break;
+            };
 
-                let prd = load_prd(&prd_path)?;
-                let completed = prd.stories.iter().filter(|s| s.passes).count() as u64;
-                progress_bar.set_position(completed);
+            let plan = ctx.plan_attempt(story);
+            let spinner = output::create_job_spinner(
+                &multi,
+                &plan.story.id,
+                &format!(
+                    "Working... (attempt {}/{})",
+                    plan.attempt, plan.max_attempts
+                ),
+            );
+
+            ctx.begin_attempt(&plan.story.id)?;
+            started += 1;
+            let worktree = match StoryWorktree::create(
+                &ctx.cwd,
+                &worktrees_dir,
+                branch,
+                &plan.story.id,
+                plan.number,
+                prd_rel,
+                progress_rel,
+            ) {
+                Ok(worktree) => worktree,
+                Err(e) => {
+                    // Counts against the story's budget like any failed attempt; the
+                    // next one gets a new branch name.
+                    let report =
+                        AttemptReport::failed(format!("Could not set up a worktree: {e:#}"));
+                    prd = record_attempt(ctx, &plan, &report, &spinner)?;
+                    progress_bar.set_position(ctx.settings.completed(&prd));
+                    continue;
+                }
+            };
+            active.insert(plan.story.id.clone());
+            running.push(run_attempt_in_worktree(ctx, plan, worktree, spinner));
+        }
+
+        let Some((plan, worktree, spinner, result)) = running.next().await else {
+            progress_bar.finish_and_clear();
+            if ctx.interrupt.is_set() {
+                // Reported by the run summary
+            } else if ctx.deadline_passed() {
+                ctx.report_deadline()?;
+            } else if started < max_iterations {
+                ctx.settings.report_queue_exhausted(&prd);
             }
-            Err(e) => {
-                output::finish_spinner_error(&spinner, &format!("Failed: {} - {}", story_id, e));
+            break;
+        };
+        active.remove(&plan.story.id);
 
-                let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-                append_progress(
-                    &progress_path,
-                    &format!("\n## [{timestamp}] Failed: {story_id}\nError: {e}\n"),
-                )?;
+        let mut report = result.unwrap_or_else(|e| AttemptReport::failed(format!("Error: {e}")));
+        let passed = matches!(report.outcome, AttemptOutcome::Passed);
+        let story_branch = worktree.branch.clone();
+        let outcome = worktree.finish(&ctx.cwd, passed)?;
+
+        if let Some(learnings) = outcome.learnings {
+            append_progress(&ctx.progress_path, &format!("\n{learnings}\n"))?;
+        }
+        match outcome.merge {
+            Some(git::MergeResult::Merged) => {
+                output::verbose(&format!("merged {} into {}", plan.story.id, branch));
             }
+            Some(git::MergeResult::Conflict { files, message }) => {
+                let detail = if files.is_empty() {
+                    message
+                } else {
+                    format!("Conflicting files: {}", files.join(", "))
+                };
+                report.outcome = AttemptOutcome::Failed;
+                report.reason = Some(format!(
+                    "Merge conflict merging {} into {}\n{}\nBranch {} was kept for manual resolution",
+                    plan.story.id, branch, detail, story_branch
+                ));
+            }
+            None => {}
         }
-    }
 
-    progress_bar.finish_and_clear();
-    print_final_summary(&prd_path)?;
+        let prd = record_attempt(ctx, &plan, &report, &spinner)?;
+        progress_bar.set_position(ctx.settings.completed(&prd));
+    }
 
     Ok(())
 }
 
-fn print_final_summary(prd_path: &PathBuf) -> Result<()> {
-    let prd = load_prd(prd_path)?;
-    let total = prd.stories.len();
-    let completed = prd.stories.iter().filter(|s| s.passes).count();
-    let remaining = total - completed;
+async fn run_attempt_in_worktree(
+    ctx: &RunContext,
+    plan: AttemptPlan,
+    worktree: StoryWorktree,
+    spinner: ProgressBar,
+) -> (
+    AttemptPlan,
+    StoryWorktree,
+    ProgressBar,
+    Result<AttemptReport>,
+) {
+    let workspace = Workspace {
+        dir: worktree.cwd().to_path_buf(),
+        prd_path: worktree.prd_path(),
+        progress_path: worktree.progress_path(),
+    };
+    let result = run_attempt(ctx, &plan, &workspace, &spinner, false).await;
+    (plan, worktree, spinner, result)
+}
 
-    output::header("=== Summary ===");
+/// Runs one attempt: the agent, then ramph's checks of what it did, then verification.
+/// Either phase is cut short if the attempt times out or an interrupt arrives.
+async fn run_attempt(
+    ctx: &RunContext,
+    plan: &AttemptPlan,
+    workspace: &Workspace,
+    spinner: &ProgressBar,
+    stream_text: bool,
+) -> Result<AttemptReport> {
+    let workdir = workspace.dir.as_path();
+    let snapshot = Snapshot::capture(
+        workdir,
+        &workspace.prd_path,
+        &workspace.progress_path,
+        &plan.story.id,
+    )?;
+    let started = Instant::now();
+    let mut trace = AttemptTrace {
+        started_at: Local::now().to_rfc3339(),
+        ..Default::default()
+    };
 
-    if remaining == 0 {
-        eprintln!(
-            "  {} All {} stories completed!",
-            "✓".green().bold(),
-            total
-        );
-    } else {
-        eprintln!(
-            "  {} {}/{} stories completed, {} remaining",
-            "•".blue().bold(),
-            completed,
-            total,
-            remaining
-        );
+    let progress = load_progress(&ctx.progress_path)?;
+    let prompt = build_iteration_prompt(
+        &ctx.settings.base_prompt,
+        &plan.story,
+        &progress,
+        &plan.verify_steps,
+    );
+    let run_id = ctx.state.borrow().run_id.clone();
+    let transcript = transcript_path(&ctx.cwd, &run_id, &plan.story.id, plan.number)?;
+    trace.transcript = transcript
+        .strip_prefix(&ctx.cwd)
+        .map(Path::to_path_buf)
+        .ok();
+    let opts = IterationOptions {
+        spinner: Some(spinner),
+        stream_text,
+        continue_thread: plan.continue_session.clone(),
+        transcript: Some(&transcript),
+        permissions: agent_permissions(&plan.permissions, workspace),
+    };
+
+    let agent_result = tokio::select! {
+        result = run_iteration(ctx.settings.agent.as_ref(), &prompt, workdir, opts, &mut trace.session) => Ok(result),
+        _ = expired(plan.timeout, started) => Err(timed_out(plan.timeout)),
+        _ = ctx.interrupt.triggered() => Err((AttemptOutcome::Interrupted, None)),
+    };
+    if agent_result.is_err() {
+        backend::terminate_agent(workdir);
     }
 
-    Ok(())
+    trace.prd_repair = snapshot.protect_prd(&workspace.prd_path, &plan.story.id)?;
+    if let Some(repair) = &trace.prd_repair {
+        output::warn(&format!("PRD guard ({}): {}", plan.story.id, repair));
+    }
+
+    let mut iteration = snapshot.classify(workdir, &workspace.prd_path, &plan.story.id)?;
+    let mut out_of_scope = None;
+    if let Some(scope) = &plan.story.scope {
+        let files = snapshot.out_of_scope(workdir, &iteration, scope)?;
+        if !files.is_empty() {
+            let listed = files.join(", ");
+            trace.scope = Some(match ctx.settings.scope_policy {
+                ScopePolicy::Warn => format!("changed outside scope: {listed}"),
+                ScopePolicy::Fail => {
+                    out_of_scope = Some(listed.clone());
+                    format!("changed outside scope: {listed}")
+                }
+                ScopePolicy::Revert => {
+                    let message = format!("ramph: revert {} changes outside scope", plan.story.id);
+                    git::restore_paths(workdir, &snapshot.head, &files, &message, true)?;
+                    iteration =
+                        snapshot.classify_net(workdir, &workspace.prd_path, &plan.story.id)?;
+                    format!("reverted changes outside scope: {listed}")
+                }
+            });
+            output::warn(&format!(
+                "Scope ({}): {}",
+                plan.story.id,
+                trace.scope.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+    output::verbose(&format!("iteration result: {}", iteration.describe()));
+    let change = iteration.change;
+    let needs_human = iteration.needs_human;
+    let changed_files = iteration.changed_files.clone();
+    trace.iteration = Some(iteration);
+
+    let (outcome, reason) = match agent_result {
+        Err(cut_short) => cut_short,
+        Ok(Err(e)) => failed(format!("Error: {e}")),
+        Ok(Ok(_)) if needs_human => (AttemptOutcome::NeedsHuman, None),
+        Ok(Ok(_)) if out_of_scope.is_some() => failed(format!(
+            "Changed files outside the story's scope: {}",
+            out_of_scope.unwrap_or_default()
+        )),
+        Ok(Ok(_)) => match change {
+            IterationChange::NoOp => {
+                failed("Iteration made no changes: nothing was committed or edited".to_string())
+            }
+            IterationChange::Uncommitted => {
+                failed("Agent changed files but did not commit them".to_string())
+            }
+            IterationChange::CommittedNotMarked if plan.verify_steps.is_empty() => {
+                failed("Story was not marked as passing".to_string())
+            }
+            _ if plan.verify_steps.is_empty() => (AttemptOutcome::Passed, None),
+            _ => {
+                spinner.set_message(format!("Verifying {}...", plan.story.id));
+                tokio::select! {
+                    results = verify::run_verification(&plan.verify_steps, workdir) => {
+                        let results = results?;
+                        if verify::all_passed(&results) {
+                            (AttemptOutcome::Passed, None)
+                        } else {
+                            let report = verify::failure_report(&results);
+                            failed(format!("Verification failed\n{report}"))
+                        }
+                    }
+                    _ = expired(plan.timeout, started) => timed_out(plan.timeout),
+                    _ = ctx.interrupt.triggered() => (AttemptOutcome::Interrupted, None),
+                }
+            }
+        },
+    };
+
+    if matches!(outcome, AttemptOutcome::Failed | AttemptOutcome::TimedOut) {
+        trace.rollback = Some(roll_back(
+            ctx.settings.on_failure,
+            plan,
+            &snapshot,
+            &changed_files,
+            workdir,
+        ));
+    }
+
+    trace.duration = started.elapsed();
+    Ok(AttemptReport {
+        outcome,
+        reason,
+        trace,
+    })
 }
 
-pub async fn run_plan_command(
-    cwd: PathBuf,
-    output_file: PathBuf,
-    description: Option<String>,
-    force: bool,
-) -> Result<()> {
-    let output_path = cwd.join(&output_file);
+/// The outcome of an attempt that failed for `reason`.
+fn failed(reason: String) -> (AttemptOutcome, Option<String>) {
+    (AttemptOutcome::Failed, Some(reason))
+}
 
-    check_output_file(&output_path, force).context("Output file validation failed")?;
+/// The outcome of an attempt stopped at its time limit.
+fn timed_out(limit: Option<Duration>) -> (AttemptOutcome, Option<String>) {
+    let limit = format_duration(limit.unwrap_or_default());
+    (
+        AttemptOutcome::TimedOut,
+        Some(format!("Timed out after {limit}")),
+    )
+}
 
-    output::header("=== ramph plan ===");
-    output::info("Starting planning conversation...");
-    output::info("The AI agent will help you break down your project into stories.\n");
+/// The attempt's policy, extended so the agent can always update the PRD and progress log.
+fn agent_permissions(policy: &PermissionPolicy, workspace: &Workspace) -> PermissionPolicy {
+    let mut policy = policy.clone();
+    if let Some(paths) = &mut policy.paths {
+        for path in [&workspace.prd_path, &workspace.progress_path] {
+            let path = path.strip_prefix(&workspace.dir).unwrap_or(path);
+            paths.push(path.to_string_lossy().to_string());
+        }
+    }
+    policy
+}
 
-    let prompt = build_planning_prompt(description);
-    let spinner = output::create_spinner("Planning session in progress...");
+/// Whether the run restricts tools at all, project-wide or for some story.
+fn restricts_permissions(settings: &RunSettings, prd: &Prd) -> bool {
+    !settings.config.permissions.is_unrestricted()
+        || prd.stories.iter().any(|s| s.permissions.is_some())
+}
 
-    let conversation = run_iteration(&prompt, &cwd, Some(&spinner))
-        .await
-        .context("Planning session failed")?;
+/// Refuses a policy the backend cannot apply, since the agent would get full access
+/// instead; `allow_unenforced` turns the refusal into a warning.
+fn check_permissions_enforced(
+    agent: &dyn AgentBackend,
+    policy: &str,
+    allow_unenforced: bool,
+) -> Result<()> {
+    if agent.enforces_permissions() {
+        return Ok(());
+    }
+    let message = format!("The {} backend cannot enforce {policy}", agent.name());
+    anyhow::ensure!(
+        allow_unenforced,
+        "{message}; use a backend that can, remove the policy, or pass \
+         --allow-unenforced-permissions to run without it"
+    );
+    output::warn(&format!("{message}; the policy is ignored"));
+    Ok(())
+}
 
-    output::finish_spinner_success(&spinner, "Planning conversation complete!");
+/// Resolves once an attempt started at `started` has used up `timeout`; never if there is none.
+async fn expired(timeout: Option<Duration>, started: Instant) {
+    match timeout {
+        Some(limit) => tokio::time::sleep_until(started + limit).await,
+        None => std::future::pending().await,
+    }
+}
 
-    output::info("Generating structured PRD from conversation...\n");
-    let extraction_spinner = output::create_spinner("Extracting PRD...");
+/// Applies the `--on-failure` policy to a failed attempt's working tree and describes what it did.
+fn roll_back(
+    policy: FailurePolicy,
+    plan: &AttemptPlan,
+    snapshot: &Snapshot,
+    changed_files: &[String],
+    workdir: &Path,
+) -> String {
+    let result = match policy {
+        FailurePolicy::Keep => Ok("kept changes".to_string()),
+        FailurePolicy::Stash => {
+            let message = format!(
+                "ramph: {} attempt {}/{} failed",
+                plan.story.id, plan.attempt, plan.max_attempts
+            );
+            git::stash_push(workdir, &message, changed_files).map(|stashed| {
+                if stashed {
+                    format!("stashed changes as \"{message}\"")
+                } else {
+                    "nothing to stash".to_string()
+                }
+            })
+        }
+        FailurePolicy::Reset => snapshot
+            .reset(workdir)
+            .map(|()| format!("reset to {}", git::short_sha(&snapshot.head))),
+    };
 
-    let extraction_prompt = build_extraction_prompt(&conversation);
-    let json_response = run_iteration(&extraction_prompt, &cwd, Some(&extraction_spinner)).await?;
+    result.unwrap_or_else(|e| {
+        output::warn(&format!("Could not roll back {}: {e}", plan.story.id));
+        format!("rollback failed: {e}")
+    })
+}
 
-    output::finish_spinner_success(&extraction_spinner, "PRD extracted!");
+/// Writes an attempt's result to the PRD and progress log and reports it.
+fn record_attempt(
+    ctx: &RunContext,
+    plan: &AttemptPlan,
+    report: &AttemptReport,
+    spinner: &ProgressBar,
+) -> Result<Prd> {
+    let story_id = &plan.story.id;
+    let story_title = &plan.story.title;
+    let (attempt, max_attempts) = (plan.attempt, plan.max_attempts);
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
 
-    let cleaned = clean_json_response(&json_response)
-        .context("Failed to extract JSON from agent response")?;
+    {
+        let mut state = ctx.state.borrow_mut();
+        if let Some(session) = &report.trace.session.id {
+            state.record_session(story_id, session);
+        }
+        if !matches!(report.outcome, AttemptOutcome::Interrupted) {
+            state.current_stories.retain(|id| id != story_id);
+        }
+    }
+    ctx.save_state()?;
 
-    let prd: Prd =
-        serde_json::from_str(&cleaned).context("Failed to parse generated PRD JSON")?;
+    let details = report.trace.details();
 
-    validate_prd(&prd).context("PRD validation failed")?;
+    let failure = match &report.outcome {
+        AttemptOutcome::Passed => None,
+        AttemptOutcome::NeedsHuman => Some((
+            "Needs human",
+            "The agent asked for a decision before continuing (see progress.txt)".to_string(),
+        )),
+        AttemptOutcome::Failed => Some(("Failed", report.reason.clone().unwrap_or_default())),
+        AttemptOutcome::TimedOut => Some(("Timed out", report.reason.clone().unwrap_or_default())),
+        AttemptOutcome::Interrupted => {
+            output::finish_spinner_error(
+                spinner,
+                &format!("Interrupted: {} - {}", story_id, story_title),
+            );
+            let session = report
+                .trace
+                .session
+                .id
+                .as_deref()
+                .map(|s| format!("Session: {s}\n"))
+                .unwrap_or_default();
+            append_progress(
+                &ctx.progress_path,
+                &format!(
+                    "\n## [{timestamp}] Interrupted: {story_id} (attempt {attempt}/{max_attempts})\n{details}{session}"
+                ),
+            )?;
+            let mut prd = load_prd(&ctx.prd_path)?;
+            if let Some(s) = prd.stories.iter_mut().find(|s| &s.id == story_id) {
+                s.attempts.push(report.to_attempt(plan.number));
+            }
+            save_prd(&ctx.prd_path, &prd)?;
+            return Ok(prd);
+        }
+    };
 
-    display_prd_summary(&prd);
+    let status = match report.outcome {
+        AttemptOutcome::Passed => StoryStatus::Passed,
+        AttemptOutcome::NeedsHuman => StoryStatus::NeedsHuman,
+        _ if attempt >= max_attempts => StoryStatus::Blocked,
+        _ => StoryStatus::Failed,
+    };
 
-    eprint!(
-        "\n{} Save this PRD to {}? (y/n): ",
-        "?".cyan().bold(),
-        output_path.display()
-    );
-    io::stdout().flush()?;
+    let mut prd = load_prd(&ctx.prd_path)?;
+    if let Some(s) = prd.stories.iter_mut().find(|s| &s.id == story_id) {
+        s.set_status(status);
+        s.attempt_count = attempt;
+        s.attempts.push(report.to_attempt(plan.number));
+    }
+    save_prd(&ctx.prd_path, &prd)?;
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    match failure {
+        None => {
+            output::finish_spinner_success(
+                spinner,
+                &format!("Completed: {} - {}", story_id, story_title),
+            );
+            append_progress(
+                &ctx.progress_path,
+                &format!("\n## [{timestamp}] Completed: {story_id}\n{details}"),
+            )?;
+        }
+        Some((label, reason)) => {
+            output::finish_spinner_error(
+                spinner,
+                &format!(
+                    "{}: {} - {} (attempt {}/{})",
+                    label, story_id, story_title, attempt, max_attempts
+                ),
+            );
+            output::verbose(&reason);
+            append_progress(
+                &ctx.progress_path,
+                &format!(
+                    "\n## [{timestamp}] {label}: {story_id} (attempt {attempt}/{max_attempts})\n{details}{reason}\n"
+                ),
+            )?;
+        }
+    }
 
-    if input.trim().to_lowercase() != "y" {
-        output::warn("PRD not saved. Exiting.");
-        return Ok(());
+    if status == StoryStatus::Blocked {
+        output::warn(&format!(
+            "{} blocked after {} failed attempts",
+            story_id, max_attempts
+        ));
+        append_progress(
+            &ctx.progress_path,
+            &format!("\n## [{timestamp}] Blocked: {story_id}\n"),
+        )?;
     }
 
-    save_prd(&output_path, &prd).context("Failed to save PRD")?;
-    output::success(&format!("PRD saved to {}!", output_path.display()));
+    Ok(prd)
+}
 
-    Ok(())
+/// Renders the prompt for every story the run would attempt, in scheduling order, assuming
+/// each one passes on its first try. Nothing is sent to the agent and git is left alone.
+fn print_dry_run(
+    settings: &RunSettings,
+    prd: &Prd,
+    progress_path: &Path,
+    deadline: Option<DateTime<Local>>,
+    prompts_dir: Option<&Path>,
+) -> Result<()> {
+    output::header("=== ramph run (dry run) ===");
+    output::info(&format!("Branch: {}", prd.branch_name));
+    output::info(&format!("Agent: {}", settings.agent.name()));
+    output::info(&format!(
+        "Tool permissions: {}",
+        settings.config.permissions.describe()
+    ));
+    if restricts_permissions(settings, prd) {
+        check_permissions_enforced(settings.agent.as_ref(), "the tool permissions policy", true)?;
+    }
+    output::info(&format!("On failure: {}", settings.on_failure));
+    if prd.stories.iter().any(|s| s.scope.is_some()) {
+        output::info(&format!("Scope policy: {}", settings.scope_policy));
+    }
+    if let Some(deadline) = deadline {
+        output::info(&format!("Deadline: {}", deadline.format("%Y-%m-%d %H:%M")));
+    }
+
+    if let Some(dir) = prompts_dir {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    let progress = load_progress(progress_path)?;
+    let mut simulated = prd.clone();
+    let mut order = 0;
+
+    while let Some(story) = settings.ready_stories(&simulated).into_iter().next() {
+        order += 1;
+        let plan = settings.plan_attempt(story);
+        let prompt = build_iteration_prompt(
+            &settings.base_prompt,
+            &plan.story,
+            &progress,
+            &plan.verify_steps,
+        );
+
+        output::header(&format!("=== {}. {} ===", order, plan.story.id));
+        output::story_status(&plan.story.id, &plan.story.title, StoryStatus::Pending);
+        output::info(&format!(
+            "Prompt: {} bytes, {} lines",
+            prompt.len(),
+            prompt.lines().count()
+        ));
+        output::info(&format!(
+            "Attempt {}/{}, timeout: {}",
+            plan.attempt,
+            plan.max_attempts,
+            plan.timeout.map_or("none".to_string(), format_duration)
+        ));
+        if plan.permissions != settings.config.permissions {
+            output::info(&format!(
+                "Tool permissions: {}",
+                plan.permissions.describe()
+            ));
+        }
+        if let Some(scope) = &plan.story.scope {
+            output::info(&format!("Scope: {}", scope.join(", ")));
+        }
+        if plan.verify_steps.is_empty() {
+            output::info("Verification: none (the agent marks the story as passing)");
+        } else {
+            for step in &plan.verify_steps {
+                output::info(&format!("Verify {}: {}", step.name, step.command));
+            }
+        }
+
+        match prompts_dir {
+            Some(dir) => {
+                let path = dir.join(format!("{:02}-{}.md", order, plan.story.id));
+                fs::write(&path, &prompt)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                output::info(&format!("Wrote {}", path.display()));
+            }
+            None => println!("----- {} -----\n{prompt}", plan.story.id),
+        }
+
+        let id = plan.story.id.clone();
+        if let Some(s) = simulated.stories.iter_mut().find(|s| s.id == id) {
+            s.status = StoryStatus::Passed;
+        }
+    }
+
+    let skipped: Vec<&Story> = simulated
+        .stories
+        .iter()
+        .filter(|s| !s.passes() && settings.selected.contains(&s.id))
+        .collect();
+    output::header("=== Summary ===");
+    output::info(&format!("{order} prompt(s) would be sent"));
+    if !skipped.is_empty() {
+        let ids: Vec<&str> = skipped.iter().map(|s| s.id.as_str()).collect();
+        output::warn(&format!(
+            "Not scheduled (blocked, or waiting on stories that will not run): {}",
+            ids.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// IDs in `selected`, in PRD order.
+fn selected_ids<'a>(prd: &'a Prd, selected: &HashSet<String>) -> Vec<&'a str> {
+    prd.stories
+        .iter()
+        .filter(|s| selected.contains(&s.id))
+        .map(|s| s.id.as_str())
+        .collect()
+}
+
+fn prepare_branch(
+    cwd: &Path,
+    branch: &str,
+    base: Option<&str>,
+    allow_dirty: bool,
+    managed: &[&Path],
+) -> Result<String> {
+    git::ensure_repo(cwd)?;
+
+    // ramph rewrites its own PRD and progress files throughout a run
+    let managed: Vec<String> = managed
+        .iter()
+        .filter_map(|p| p.strip_prefix(cwd).ok())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    let managed: Vec<&str> = managed.iter().map(String::as_str).collect();
+    if git::is_dirty(cwd, &managed)? {
+        if !allow_dirty {
+            anyhow::bail!(
+                "Working tree has uncommitted changes. Commit or stash them first, or pass --allow-dirty."
+            );
+        }
+        output::warn("Working tree has uncommitted changes (--allow-dirty)");
+    }
+
+    let checkout = git::checkout_branch(cwd, branch, base)
+        .with_context(|| format!("Failed to check out branch {branch}"))?;
+    match checkout {
+        git::BranchCheckout::Created => output::info(&format!(
+            "Created branch {} from {}",
+            branch,
+            base.unwrap_or("HEAD")
+        )),
+        git::BranchCheckout::Switched => output::info(&format!("Switched to branch {branch}")),
+        git::BranchCheckout::AlreadyOn => output::info(&format!("On branch {branch}")),
+    }
+    if let Some(base) = base
+        && checkout != git::BranchCheckout::Created
+    {
+        output::warn(&format!(
+            "Branch {branch} already exists; ignoring --base {base}"
+        ));
+    }
+
+    let start_commit = git::head_commit(cwd)?;
+    output::verbose(&format!("start commit: {start_commit}"));
+    Ok(start_commit)
+}
+
+fn print_final_summary(
+    prd_path: &Path,
+    cwd: &Path,
+    start_commit: &str,
+    group_by_tag: bool,
+) -> Result<()> {
+    let prd = load_prd(prd_path)?;
+    if group_by_tag {
+        display_prd_summary(&prd, true);
+    }
+    let total = prd.stories.len();
+    let completed = prd.stories.iter().filter(|s| s.passes()).count();
+    let remaining = total - completed;
+
+    output::header("=== Summary ===");
+
+    if remaining == 0 {
+        eprintln!(
+            "  {} All {} stories completed!",
+            "✓".green().bold(),
+            total
+        );
+    } else {
+        eprintln!(
+            "  {} {}/{} stories completed, {} remaining",
+            "•".blue().bold(),
+            completed,
+            total,
+            remaining
+        );
+    }
+
+    print_stuck_stories(&prd);
+
+    let commits = git::commit_count_since(cwd, start_commit)?;
+    eprintln!(
+        "  {} {} commit(s) on {} since {}",
+        "•".blue().bold(),
+        commits,
+        prd.branch_name,
+        git::short_sha(start_commit)
+    );
+
+    Ok(())
+}
+
+/// Lists blocked stories, stories waiting for a human and the stories held up by either.
+fn print_stuck_stories(prd: &Prd) {
+    let blocked: Vec<&Story> = prd
+        .stories
+        .iter()
+        .filter(|s| s.status == StoryStatus::Blocked)
+        .collect();
+    if !blocked.is_empty() {
+        eprintln!(
+            "  {} {} blocked stor{}:",
+            "✗".red().bold(),
+            blocked.len(),
+            if blocked.len() == 1 { "y" } else { "ies" }
+        );
+        for story in blocked {
+            eprintln!(
+                "      {} {} ({} attempts)",
+                story.id.bold(),
+                story.title,
+                story.attempt_count
+            );
+        }
+    }
+
+    let needs_human: Vec<&Story> = prd
+        .stories
+        .iter()
+        .filter(|s| s.status == StoryStatus::NeedsHuman)
+        .collect();
+    if !needs_human.is_empty() {
+        eprintln!(
+            "  {} {} stor{} waiting for a human decision (see the progress log):",
+            output::status_icon(StoryStatus::NeedsHuman),
+            needs_human.len(),
+            if needs_human.len() == 1 { "y" } else { "ies" }
+        );
+        for story in needs_human {
+            eprintln!("      {} {}", story.id.bold(), story.title);
+        }
+    }
+
+    let waiting = prd.blocked_dependents();
+    if !waiting.is_empty() {
+        eprintln!(
+            "  {} {} stor{} waiting on dependencies that cannot run:",
+            "⚠".yellow().bold(),
+            waiting.len(),
+            if waiting.len() == 1 { "y" } else { "ies" }
+        );
+        for story in waiting {
+            eprintln!(
+                "      {} {} (depends on {})",
+                story.id.bold(),
+                story.title,
+                story.depends_on.join(", ")
+            );
+        }
+    }
+}
+
+pub struct PlanOptions {
+    pub cwd: PathBuf,
+    pub output: PathBuf,
+    pub config: PathBuf,
+    pub description: Option<String>,
+    pub force: bool,
+    /// Skip the conversation and extract the PRD from this saved conversation
+    pub extract_from: Option<PathBuf>,
+    /// How many times the agent may correct an invalid PRD
+    pub repair_attempts: u32,
+    /// Plan additional stories for the existing PRD instead of writing a new one
+    pub amend: bool,
+    /// Leave the codebase snapshot out of the planning prompt
+    pub no_context: bool,
+    /// Plan even if the backend cannot enforce `plan_permissions`
+    pub allow_unenforced_permissions: bool,
+    /// Show the proposed PRD grouped by tag
+    pub group_by_tag: bool,
+}
+
+pub async fn run_plan_command(options: PlanOptions) -> Result<()> {
+    plan(options, &mut io::stdin().lock()).await
+}
+
+/// `run_plan_command` with the user's replies and confirmation read from `input`.
+async fn plan(options: PlanOptions, input: &mut dyn BufRead) -> Result<()> {
+    let PlanOptions {
+        cwd,
+        output: output_file,
+        config,
+        description,
+        force,
+        extract_from,
+        repair_attempts,
+        amend,
+        no_context,
+        allow_unenforced_permissions,
+        group_by_tag,
+    } = options;
+    let output_path = cwd.join(&output_file);
+
+    let existing = if amend {
+        let prd = load_prd(&output_path)
+            .with_context(|| format!("Cannot amend {}", output_path.display()))?;
+        validate_prd(&prd).context("Existing PRD validation failed")?;
+        Some(prd)
+    } else {
+        check_output_file(&output_path, force).context("Output file validation failed")?;
+        None
+    };
+    let config = load_config(&cwd.join(&config))?;
+    let agent = backend::from_config(&config.agent, &cwd)?;
+    let permissions = config
+        .plan_permissions
+        .clone()
+        .unwrap_or_else(PermissionPolicy::read_only);
+
+    output::header("=== ramph plan ===");
+    if !permissions.is_unrestricted() {
+        // Only a policy set in the config is refused; the read-only default just warns
+        check_permissions_enforced(
+            agent.as_ref(),
+            &format!("the planning tool permissions ({})", permissions.describe()),
+            allow_unenforced_permissions || config.plan_permissions.is_none(),
+        )?;
+    }
+    let conversation_path = match extract_from {
+        Some(path) => cwd.join(path),
+        None => {
+            match &existing {
+                Some(prd) => output::info(&format!(
+                    "Amending {} ({} stories, {} passed); new stories start at {}",
+                    output_path.display(),
+                    prd.stories.len(),
+                    prd.stories.iter().filter(|s| s.passes()).count(),
+                    next_story_id(prd)
+                )),
+                None => output::info("Starting planning conversation..."),
+            }
+            output::info("The AI agent will help you break down your project into stories.");
+            output::info("Reply to its questions; /done extracts the PRD, /help lists commands.\n");
+
+            let snapshot = if no_context {
+                None
+            } else {
+                codebase_snapshot(&cwd, &config.plan_context)
+            };
+            let path = new_conversation_path(&cwd)?;
+            let planning_prompt =
+                build_planning_prompt(description, existing.as_ref(), snapshot.as_deref());
+            plan_conversation(
+                agent.as_ref(),
+                &cwd,
+                &permissions,
+                planning_prompt,
+                &path,
+                input,
+            )
+            .await?;
+            output::success("Planning conversation complete!");
+            output::info(&format!("Conversation saved to {}", path.display()));
+            path
+        }
+    };
+    let conversation = Conversation::load(&conversation_path)?;
+    let retry_hint = || {
+        format!(
+            "The conversation is saved; retry with: ramph plan --extract-from {}",
+            conversation_path.display()
+        )
+    };
+
+    output::info("Generating structured PRD from conversation...\n");
+    let extraction_prompt = build_extraction_prompt(&conversation.render(), existing.as_ref());
+    let mut prompt = extraction_prompt.clone();
+    let mut repairs = 0;
+
+    let amendment = loop {
+        let spinner = output::create_spinner("Extracting PRD...");
+        let opts = IterationOptions {
+            spinner: Some(&spinner),
+            stream_text: true,
+            permissions: permissions.clone(),
+            ..Default::default()
+        };
+        let mut session = SessionInfo::default();
+        let response = run_iteration(agent.as_ref(), &prompt, &cwd, opts, &mut session)
+            .await
+            .with_context(retry_hint)?;
+
+        let parsed = parse_prd_response(&response).and_then(|prd| match &existing {
+            Some(existing) => {
+                let amendment = amend_prd(existing, prd);
+                validate_prd(&amendment.prd).context("Amended PRD validation failed")?;
+                Ok(amendment)
+            }
+            None => Ok(Amendment {
+                prd,
+                notes: Vec::new(),
+            }),
+        });
+        let error = match parsed {
+            Ok(amendment) => {
+                output::finish_spinner_success(&spinner, "PRD extracted!");
+                break amendment;
+            }
+            Err(e) => format!("{e:#}"),
+        };
+        output::finish_spinner_error(&spinner, "Extracted PRD is invalid");
+        output::warn(&error);
+
+        if repairs >= repair_attempts {
+            anyhow::bail!(
+                "PRD extraction failed after {repairs} repair attempt(s): {error}\n{}",
+                retry_hint()
+            );
+        }
+        repairs += 1;
+        output::info(&format!(
+            "Sending the error back to the agent ({repairs}/{repair_attempts})...\n"
+        ));
+        prompt = build_extraction_repair_prompt(&extraction_prompt, &response, &error);
+    };
+    let prd = amendment.prd;
+
+    display_prd_summary(&prd, group_by_tag);
+    if let Some(existing) = &existing {
+        for note in &amendment.notes {
+            output::warn(&format!("Adjusted the proposal: {note}"));
+        }
+        let diff = PrdDiff::between(existing, &prd);
+        if diff.is_empty() {
+            output::info("The amended PRD is identical to the current one; nothing to save.");
+            return Ok(());
+        }
+        print_prd_diff(existing, &prd, &diff);
+    }
+
+    eprint!(
+        "\n{} Save this PRD to {}? (y/n): ",
+        "?".cyan().bold(),
+        output_path.display()
+    );
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    if answer.trim().to_lowercase() != "y" {
+        output::warn("PRD not saved. Exiting.");
+        return Ok(());
+    }
+
+    save_prd(&output_path, &prd).context("Failed to save PRD")?;
+    output::success(&format!("PRD saved to {}!", output_path.display()));
+
+    Ok(())
+}
+
+/// A change made to the PRD with `ramph story`.
+pub enum StoryAction {
+    Add {
+        id: Option<String>,
+        edit: StoryEdit,
+    },
+    Edit {
+        id: String,
+        edit: StoryEdit,
+    },
+    Remove {
+        ids: Vec<String>,
+        force: bool,
+    },
+    Reorder {
+        ids: Vec<String>,
+    },
+    Reset {
+        selection: StorySelection,
+        all: bool,
+    },
+}
+
+/// Applies `action` to the PRD at `prd`, saving it only if the result validates. The PRD
+/// isn't validated beforehand so these commands can also repair one that no longer loads
+/// into a run.
+pub fn run_story_command(cwd: PathBuf, prd: PathBuf, action: StoryAction) -> Result<()> {
+    let prd_path = cwd.join(&prd);
+    let before = load_prd(&prd_path)?;
+    let mut after = before.clone();
+
+    let message = match action {
+        StoryAction::Add { id, edit } => {
+            let id = id.unwrap_or_else(|| next_story_id(&after));
+            anyhow::ensure!(
+                !after.stories.iter().any(|s| s.id == id),
+                "Story {} already exists",
+                id
+            );
+            let mut story = Story {
+                id: id.clone(),
+                priority: after.stories.iter().map(|s| s.priority).max().unwrap_or(0) + 1,
+                ..Default::default()
+            };
+            edit.apply(&mut story);
+            after.stories.push(story);
+            format!("Added {id}")
+        }
+        StoryAction::Edit { id, edit } => {
+            anyhow::ensure!(
+                !edit.is_empty(),
+                "Nothing to change: pass at least one field to edit"
+            );
+            let story = after
+                .stories
+                .iter_mut()
+                .find(|s| s.id == id)
+                .with_context(|| format!("Unknown story ID: {id}"))?;
+            edit.apply(story);
+            format!("Updated {id}")
+        }
+        StoryAction::Remove { ids, force } => {
+            for id in &ids {
+                anyhow::ensure!(
+                    after.stories.iter().any(|s| &s.id == id),
+                    "Unknown story ID: {}",
+                    id
+                );
+            }
+            let removed: HashSet<String> = ids.iter().cloned().collect();
+            let dependents: Vec<String> = after
+                .dependents_of(&removed)
+                .iter()
+                .map(|s| s.id.clone())
+                .collect();
+            if !dependents.is_empty() {
+                anyhow::ensure!(
+                    force,
+                    "Other stories depend on {}: {}; pass --force to drop those dependencies too",
+                    ids.join(", "),
+                    dependents.join(", ")
+                );
+                output::warn(&format!(
+                    "Dropping dependencies of {} on {}",
+                    dependents.join(", "),
+                    ids.join(", ")
+                ));
+            }
+            after.remove_stories(&removed);
+            format!("Removed {}", ids.join(", "))
+        }
+        StoryAction::Reorder { ids } => {
+            after.renumber(&ids)?;
+            "Renumbered story priorities".to_string()
+        }
+        StoryAction::Reset { selection, all } => {
+            anyhow::ensure!(
+                all || !selection.is_empty(),
+                "Name the stories to reset (IDs or --tag), or pass --all"
+            );
+            let selected = after.select(&selection)?;
+            let reset = after.reset_stories(&selected);
+            format!("Reset {} to pending", reset.join(", "))
+        }
+    };
+
+    validate_prd(&after).context("Refusing to save an invalid PRD")?;
+
+    let diff = PrdDiff::between(&before, &after);
+    let reordered = !before
+        .stories
+        .iter()
+        .map(|s| &s.id)
+        .eq(after.stories.iter().map(|s| &s.id));
+    if diff.is_empty() && !reordered {
+        output::info("Nothing to change");
+        return Ok(());
+    }
+
+    print_prd_diff(&before, &after, &diff);
+    save_prd(&prd_path, &after)?;
+    output::success(&message);
+    Ok(())
+}
+
+/// `ramph status --json` output.
+#[derive(Serialize)]
+struct StatusReport<'a> {
+    branch: &'a str,
+    /// Why the PRD would be rejected by `ramph run`, if it would
+    validation_error: Option<String>,
+    totals: StatusTotals,
+    /// The story `ramph run` would start next
+    next_story: Option<&'a str>,
+    blocked: Vec<&'a str>,
+    needs_human: Vec<&'a str>,
+    /// Stories waiting on a blocked, skipped or needs_human dependency
+    waiting: Vec<&'a str>,
+    last_run: Option<RunState>,
+    stories: Vec<StoryReport<'a>>,
+}
+
+#[derive(Serialize)]
+struct StatusTotals {
+    stories: usize,
+    /// Story count per status, every status included
+    by_status: BTreeMap<String, usize>,
+    attempts: usize,
+    /// Time spent in all recorded attempts
+    duration_ms: u64,
+}
+
+#[derive(Serialize)]
+struct StoryReport<'a> {
+    id: &'a str,
+    title: &'a str,
+    status: StoryStatus,
+    priority: i32,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    depends_on: &'a [String],
+    attempt_count: u32,
+    last_attempt: Option<&'a Attempt>,
+}
+
+/// Where `prd` stands, as `ramph status --json` reports it.
+fn status_report(prd: &Prd, last_run: Option<RunState>) -> StatusReport<'_> {
+    let with_status = |status: StoryStatus| {
+        prd.stories
+            .iter()
+            .filter(|s| s.status == status)
+            .map(|s| s.id.as_str())
+            .collect()
+    };
+    let attempts: Vec<&Attempt> = prd.stories.iter().flat_map(|s| &s.attempts).collect();
+
+    StatusReport {
+        branch: &prd.branch_name,
+        validation_error: validate_prd(prd).err().map(|e| format!("{e:#}")),
+        totals: StatusTotals {
+            stories: prd.stories.len(),
+            by_status: StoryStatus::ALL
+                .iter()
+                .map(|status| {
+                    let count = prd.stories.iter().filter(|s| s.status == *status).count();
+                    (status.to_string(), count)
+                })
+                .collect(),
+            attempts: attempts.len(),
+            duration_ms: attempts.iter().map(|a| a.duration_ms).sum(),
+        },
+        next_story: prd.ready_stories().first().map(|s| s.id.as_str()),
+        blocked: with_status(StoryStatus::Blocked),
+        needs_human: with_status(StoryStatus::NeedsHuman),
+        waiting: prd
+            .blocked_dependents()
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect(),
+        last_run,
+        stories: prd
+            .stories
+            .iter()
+            .map(|s| StoryReport {
+                id: &s.id,
+                title: &s.title,
+                status: s.status,
+                priority: s.priority,
+                tags: &s.tags,
+                depends_on: &s.depends_on,
+                attempt_count: s.attempt_count,
+                last_attempt: s.attempts.last(),
+            })
+            .collect(),
+    }
+}
+
+/// Prints where the PRD stands: the story summary, totals, what runs next, what is stuck
+/// and how the last run ended.
+pub fn run_status_command(
+    cwd: PathBuf,
+    prd: PathBuf,
+    json: bool,
+    group_by_tag: bool,
+) -> Result<()> {
+    let prd = load_prd(&cwd.join(&prd))?;
+    let report = status_report(&prd, state::load_state(&cwd)?);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    display_prd_summary(&prd, group_by_tag);
+    if let Some(error) = &report.validation_error {
+        output::warn(&format!("ramph run would reject this PRD: {error}"));
+    }
+
+    output::header("=== Status ===");
+    let total = prd.stories.len();
+    let completed = prd.stories.iter().filter(|s| s.passes()).count();
+    eprintln!(
+        "  {} {}/{} stories completed, {} remaining",
+        "•".blue().bold(),
+        completed,
+        total,
+        total - completed
+    );
+    if report.totals.attempts > 0 {
+        eprintln!(
+            "  {} {} attempt(s), {} in total",
+            "•".blue().bold(),
+            report.totals.attempts,
+            format_duration(Duration::from_millis(report.totals.duration_ms))
+        );
+    }
+    match report
+        .next_story
+        .and_then(|id| prd.stories.iter().find(|s| s.id == id))
+    {
+        Some(story) => eprintln!(
+            "  {} Next story: {} {}",
+            "→".cyan().bold(),
+            story.id.bold(),
+            story.title
+        ),
+        None if completed < total => eprintln!(
+            "  {} No story can start until the stuck ones below are dealt with",
+            "⚠".yellow().bold()
+        ),
+        None => {}
+    }
+    print_stuck_stories(&prd);
+
+    if let Some(run) = report.last_run {
+        let started = local_time(&run.started_at).unwrap_or(run.started_at.clone());
+        let updated = local_time(&run.updated_at).unwrap_or(run.updated_at.clone());
+        let status = match run.status {
+            RunStatus::Running => "running".to_string(),
+            RunStatus::Interrupted => {
+                "interrupted (continue with `ramph run --resume`)".to_string()
+            }
+            RunStatus::Finished => "finished".to_string(),
+        };
+        eprintln!(
+            "  {} Last run {}: {}, {} iteration(s), started {}, last update {}",
+            "•".blue().bold(),
+            run.run_id,
+            status,
+            run.iteration,
+            started,
+            updated
+        );
+        if !run.current_stories.is_empty() {
+            eprintln!(
+                "      {} {}",
+                "In flight:".dimmed(),
+                run.current_stories.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_logs_command(
+    cwd: PathBuf,
+    story_id: String,
+    run_id: Option<String>,
+    attempt: Option<u32>,
+) -> Result<()> {
+    let transcripts = find_transcripts(&cwd, &story_id, run_id.as_deref(), attempt)?;
+
+    for (i, transcript) in transcripts.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{}",
+            format!(
+                "=== {} attempt {} (run {}) ===",
+                transcript.story_id, transcript.attempt, transcript.run_id
+            )
+            .bold()
+        );
+        for entry in read_transcript(&transcript.path)? {
+            print_transcript_entry(&entry);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_transcript_entry(entry: &TranscriptEntry) {
+    let time = DateTime::parse_from_rfc3339(&entry.timestamp)
+        .map(|t| t.with_timezone(&Local).format("%H:%M:%S").to_string())
+        .unwrap_or_else(|_| entry.timestamp.clone());
+    let time = format!("[{time}]").dimmed();
+    let indented = |text: &str| {
+        text.trim_end()
+            .lines()
+            .map(|line| format!("    {line}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    match &entry.event {
+        AgentEvent::Prompt { text } => {
+            println!("{time} {}", "prompt".cyan().bold());
+            println!("{}", indented(text));
+        }
+        AgentEvent::Session { id } => println!("{time} {} {id}", "session".cyan().bold()),
+        AgentEvent::Text { text } => {
+            println!("{time} {}", "assistant".green().bold());
+            println!("{}", indented(text));
+        }
+        AgentEvent::ToolUse { id, name, input } => {
+            println!("{time} {} {name} {}", "tool".yellow().bold(), id.dimmed());
+            let input = serde_json::to_string_pretty(input).unwrap_or_default();
+            println!("{}", indented(&input));
+        }
+        AgentEvent::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+        } => {
+            let label = if *is_error {
+                "tool error".red().bold()
+            } else {
+                "tool result".yellow().bold()
+            };
+            println!("{time} {label} {}", tool_use_id.dimmed());
+            println!("{}", indented(content));
+        }
+        AgentEvent::Result {
+            is_error,
+            error,
+            duration_ms,
+            num_turns,
+        } => {
+            let status = if *is_error {
+                format!("failed: {}", error.as_deref().unwrap_or("unknown error")).red()
+            } else {
+                "ok".green()
+            };
+            println!(
+                "{time} {} {status} ({}, {num_turns} turn(s))",
+                "done".bold(),
+                format_duration(Duration::from_millis(*duration_ms))
+            );
+        }
+        AgentEvent::Raw { message } => {
+            if output::is_verbose() {
+                println!("{time} {}", "raw".dimmed().bold());
+                let message = serde_json::to_string_pretty(message).unwrap_or_default();
+                println!("{}", indented(&message).dimmed());
+            }
+        }
+    }
+}
+
+pub fn run_replay_command(
+    cwd: PathBuf,
+    story_id: String,
+    run_id: Option<String>,
+    attempt: Option<u32>,
+) -> Result<()> {
+    let transcripts = find_transcripts(&cwd, &story_id, run_id.as_deref(), attempt)?;
+
+    output::header("=== ramph replay ===");
+    for transcript in &transcripts {
+        output::info(&format!(
+            "Run {}, attempt {}",
+            transcript.run_id, transcript.attempt
+        ));
+        let spinner = output::create_spinner(&format!("Working on {}...", story_id));
+
+        // `None` until the transcript's result event, then the error it reported (if any)
+        let mut result: Option<Option<String>> = None;
+        for entry in read_transcript(&transcript.path)? {
+            render_event(&entry.event, Some(&spinner), true);
+            if let AgentEvent::Result {
+                is_error, error, ..
+            } = entry.event
+            {
+                result = Some(is_error.then(|| error.unwrap_or_default()));
+            }
+        }
+
+        let label = format!("{} attempt {}", story_id, transcript.attempt);
+        match result {
+            Some(None) => output::finish_spinner_success(&spinner, &label),
+            Some(Some(error)) => {
+                output::finish_spinner_error(&spinner, &format!("{label}: agent error: {error}"))
+            }
+            None => output::finish_spinner_error(
+                &spinner,
+                &format!("{label}: transcript ends without a result (attempt was cut short)"),
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Markdown overview of the project for the planning prompt, or `None` if there is
+/// nothing to show or it could not be collected.
+fn codebase_snapshot(cwd: &Path, config: &PlanContextConfig) -> Option<String> {
+    match collect_snapshot(cwd, config) {
+        Ok(Some(snapshot)) => {
+            output::info(&format!(
+                "Sharing a project snapshot with the agent: {} files, {} included in full ({} KB)",
+                snapshot.file_count,
+                snapshot.included.len(),
+                snapshot.markdown.len().div_ceil(1024)
+            ));
+            if !snapshot.omitted.is_empty() {
+                output::warn(&format!(
+                    "Left out of the snapshot (over plan_context.max_bytes): {}",
+                    snapshot.omitted.join(", ")
+                ));
+            }
+            output::verbose(&snapshot.markdown);
+            Some(snapshot.markdown)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            output::warn(&format!("Planning without a project snapshot: {e:#}"));
+            None
+        }
+    }
+}
+
+/// Story-level preview of a PRD change, for `plan --amend` and `ramph story`.
+fn print_prd_diff(before: &Prd, after: &Prd, diff: &PrdDiff) {
+    if output::is_quiet() {
+        return;
+    }
+    let title = |prd: &Prd, id: &str| {
+        prd.stories
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.title.clone())
+            .unwrap_or_default()
+    };
+
+    eprintln!("{}", "=== Changes ===".bold());
+    for id in &diff.added {
+        eprintln!(
+            "  {} {} {}",
+            "+".green().bold(),
+            id.bold(),
+            title(after, id)
+        );
+    }
+    for (id, fields) in &diff.changed {
+        eprintln!(
+            "  {} {} {} {}",
+            "~".yellow().bold(),
+            id.bold(),
+            title(after, id),
+            format!("({})", fields.join(", ")).dimmed()
+        );
+    }
+    for id in &diff.removed {
+        eprintln!("  {} {} {}", "-".red().bold(), id.bold(), title(before, id));
+    }
+    eprintln!(
+        "  {} added, {} changed, {} removed",
+        diff.added.len(),
+        diff.changed.len(),
+        diff.removed.len()
+    );
+}
+
+/// Parses and validates the agent's extraction output.
+fn parse_prd_response(response: &str) -> Result<Prd> {
+    let cleaned =
+        clean_json_response(response).context("Failed to extract JSON from agent response")?;
+    let prd: Prd = serde_json::from_str(&cleaned).context("Failed to parse generated PRD JSON")?;
+    validate_prd(&prd).context("PRD validation failed")?;
+    Ok(prd)
+}
+
+/// Runs the planning conversation until the user types `/done` (or closes stdin),
+/// saving it to `save_to` after every turn.
+///
+/// Each reply goes to the agent's own session when it reports one; otherwise the whole
+/// conversation is sent again with the next turn.
+async fn plan_conversation(
+    agent: &dyn AgentBackend,
+    cwd: &Path,
+    permissions: &PermissionPolicy,
+    planning_prompt: String,
+    save_to: &Path,
+    input: &mut dyn BufRead,
+) -> Result<Conversation> {
+    let mut conversation = Conversation::default();
+    let mut session: Option<String> = None;
+    let mut prompt = planning_prompt.clone();
+
+    loop {
+        let spinner = output::create_spinner("Agent is thinking...");
+        let opts = IterationOptions {
+            spinner: Some(&spinner),
+            stream_text: true,
+            continue_thread: session.clone(),
+            permissions: permissions.clone(),
+            ..Default::default()
+        };
+        let mut reported = SessionInfo::default();
+        let reply = run_iteration(agent, &prompt, cwd, opts, &mut reported)
+            .await
+            .context("Planning session failed")?;
+        spinner.finish_and_clear();
+        if !output::is_quiet() && !reply.ends_with('\n') {
+            println!();
+        }
+        session = reported.id.or(session);
+        conversation.push(Speaker::Agent, &reply);
+        conversation.save(save_to)?;
+
+        let reply = loop {
+            let Some(line) = read_user_reply(input)? else {
+                return Ok(conversation);
+            };
+            match line.as_str() {
+                "" => {}
+                "/done" => return Ok(conversation),
+                "/show" => show_conversation(&conversation),
+                "/restart" => break None,
+                "/help" => {
+                    output::info("/done     finish the conversation and extract the PRD");
+                    output::info("/show     print the conversation so far");
+                    output::info("/restart  discard the conversation and start over");
+                }
+                command if command.starts_with('/') => {
+                    output::warn(&format!("Unknown command {command}; /help lists commands"))
+                }
+                _ => break Some(line),
+            }
+        };
+
+        match reply {
+            Some(text) => {
+                conversation.push(Speaker::User, &text);
+                conversation.save(save_to)?;
+                prompt = match session {
+                    Some(_) => text,
+                    None => {
+                        build_planning_followup_prompt(&planning_prompt, &conversation.render())
+                    }
+                };
+            }
+            None => {
+                output::info("Restarting the planning conversation...");
+                conversation = Conversation::default();
+                session = None;
+                prompt = planning_prompt.clone();
+            }
+        }
+    }
+}
+
+/// Reads one line from the user; `None` once `input` is closed.
+fn read_user_reply(input: &mut dyn BufRead) -> Result<Option<String>> {
+    eprint!("\n{} ", "you ›".cyan().bold());
+    io::stderr().flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        eprintln!();
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+fn show_conversation(conversation: &Conversation) {
+    for turn in &conversation.turns {
+        let label = match turn.speaker {
+            Speaker::User => "you".cyan().bold(),
+            Speaker::Agent => "agent".green().bold(),
+        };
+        eprintln!("\n{label}\n{}", turn.text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::GitRepo;
+
+    /// A throwaway git repository with a PRD, a config selecting the fake backend and the
+    /// given fake agent script. Removed when dropped.
+    struct TestRepo {
+        repo: GitRepo,
+    }
+
+    impl std::ops::Deref for TestRepo {
+        type Target = GitRepo;
+
+        fn deref(&self) -> &GitRepo {
+            &self.repo
+        }
+    }
+
+    impl TestRepo {
+        fn new(prd: serde_json::Value, script: serde_json::Value) -> Self {
+            let repo = Self {
+                repo: GitRepo::new(),
+            };
+            repo.write("prd.json", &prd);
+            repo.write(
+                "ramph.json",
+                &serde_json::json!({"agent": {"backend": "fake", "script": "fake.json"}}),
+            );
+            repo.write("fake.json", &script);
+            repo.git(&["add", "-A"]);
+            repo.git(&["commit", "-q", "-m", "setup"]);
+            repo
+        }
+
+        fn write(&self, name: &str, value: &serde_json::Value) {
+            self.write_file(name, &serde_json::to_string_pretty(value).unwrap());
+        }
+
+        /// Adds `settings` to the committed `ramph.json`, keeping the fake backend selected.
+        fn configure(&self, settings: serde_json::Value) {
+            let mut config =
+                serde_json::json!({"agent": {"backend": "fake", "script": "fake.json"}});
+            for (key, value) in settings.as_object().unwrap() {
+                config[key] = value.clone();
+            }
+            self.write("ramph.json", &config);
+            self.git(&["commit", "-qam", "configure"]);
+        }
+
+        fn options(&self) -> RunOptions {
+            RunOptions {
+                cwd: self.dir.clone(),
+                prd: PathBuf::from("prd.json"),
+                progress: None,
+                prompt: None,
+                config: PathBuf::from(crate::config::DEFAULT_CONFIG_FILE),
+                max_iterations: 10,
+                max_attempts: None,
+                jobs: 1,
+                base: None,
+                allow_dirty: false,
+                resume: false,
+                iteration_timeout: None,
+                deadline: None,
+                on_failure: None,
+                scope_policy: None,
+                dry_run: false,
+                prompts_dir: None,
+                selection: StorySelection::default(),
+                force: false,
+                allow_unenforced_permissions: false,
+                group_by_tag: false,
+            }
+        }
+
+        /// Options for planning a new PRD into `planned.json`.
+        fn plan_options(&self) -> PlanOptions {
+            PlanOptions {
+                cwd: self.dir.clone(),
+                output: PathBuf::from("planned.json"),
+                config: PathBuf::from(crate::config::DEFAULT_CONFIG_FILE),
+                description: Some("A todo app".to_string()),
+                force: false,
+                extract_from: None,
+                repair_attempts: 3,
+                amend: false,
+                no_context: true,
+                allow_unenforced_permissions: false,
+                group_by_tag: false,
+            }
+        }
+
+        fn prd(&self) -> Prd {
+            load_prd(&self.dir.join("prd.json")).unwrap()
+        }
+
+        fn story(&self, id: &str) -> Story {
+            self.prd().stories.into_iter().find(|s| s.id == id).unwrap()
+        }
+
+        fn progress(&self) -> String {
+            self.read_file("progress.txt")
+        }
+    }
+
+    fn story(id: &str, priority: i32, depends_on: &[&str]) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "title": format!("Story {id}"),
+            "description": "Do the thing",
+            "priority": priority,
+            "acceptance_criteria": ["It works"],
+            "depends_on": depends_on,
+        })
+    }
+
+    fn prd(stories: Vec<serde_json::Value>) -> serde_json::Value {
+        serde_json::json!({"branchName": "feature/test", "stories": stories})
+    }
+
+    /// A scripted response that commits a file named after the story and marks it passed.
+    fn commit_and_pass(story: &str) -> serde_json::Value {
+        serde_json::json!({
+            "story": story,
+            "commands": [format!("echo {story} > {story}.txt && git add {story}.txt && git commit -qm 'Add {story}'")],
+            "mark_passed": true,
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_stories_in_dependency_order() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 2, &["B"]), story("B", 1, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A"), commit_and_pass("B")]}),
+        );
+
+        run_command(repo.options()).await.unwrap();
+
+        for id in ["A", "B"] {
+            let story = repo.story(id);
+            assert_eq!(story.status, StoryStatus::Passed);
+            assert_eq!(story.attempts.len(), 1);
+            assert_eq!(story.attempts[0].outcome, AttemptOutcome::Passed);
+            assert!(story.attempts[0].commit.is_some());
+        }
+        assert_eq!(
+            repo.git(&["rev-parse", "--abbrev-ref", "HEAD"]),
+            "feature/test"
+        );
+        assert_eq!(repo.git(&["log", "--format=%s", "-n", "2"]), "Add A\nAdd B");
+        assert!(repo.progress().contains("committed and passed"));
+    }
+
+    #[tokio::test]
+    async fn logs_and_replay_read_the_run_transcript() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+
+        run_command(repo.options()).await.unwrap();
+
+        let transcripts = find_transcripts(&repo.dir, "A", None, None).unwrap();
+        assert_eq!(transcripts.len(), 1);
+        let entries = read_transcript(&transcripts[0].path).unwrap();
+        assert!(matches!(entries[0].event, AgentEvent::Prompt { .. }));
+        assert!(matches!(
+            entries.last().unwrap().event,
+            AgentEvent::Result {
+                is_error: false,
+                ..
+            }
+        ));
+        let relative = transcripts[0].path.strip_prefix(&repo.dir).unwrap();
+        assert!(repo.progress().contains(&relative.display().to_string()));
+
+        run_logs_command(repo.dir.clone(), "A".to_string(), None, None).unwrap();
+        run_replay_command(repo.dir.clone(), "A".to_string(), None, Some(1)).unwrap();
+        assert!(run_logs_command(repo.dir.clone(), "A".to_string(), None, Some(2)).is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_permissions_the_backend_cannot_enforce() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+        repo.configure(serde_json::json!({"permissions": {"deny_tools": ["web_search"]}}));
+
+        let error = run_command(repo.options()).await.unwrap_err();
+        assert!(error.to_string().contains("cannot enforce"), "{error:#}");
+        assert_eq!(repo.story("A").status, StoryStatus::Pending);
+
+        let options = RunOptions {
+            allow_unenforced_permissions: true,
+            ..repo.options()
+        };
+        run_command(options).await.unwrap();
+        assert_eq!(repo.story("A").status, StoryStatus::Passed);
+    }
+
+    #[tokio::test]
+    async fn blocks_a_failing_story_and_its_dependents() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[]), story("B", 2, &["A"])]),
+            serde_json::json!({"responses": [{"story": "A", "error": "tool crashed"}]}),
+        );
+        let options = RunOptions {
+            max_attempts: Some(2),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Blocked);
+        assert_eq!(a.attempt_count, 2);
+        let numbers: Vec<u32> = a.attempts.iter().map(|at| at.number).collect();
+        assert_eq!(numbers, [1, 2]);
+        assert!(
+            a.attempts[0]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("tool crashed")
+        );
+        assert_eq!(repo.story("B").status, StoryStatus::Pending);
+        assert!(repo.story("B").attempts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn uncommitted_work_fails_and_is_reset() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [{"commands": ["echo draft > draft.txt"]}]}),
+        );
+        let options = RunOptions {
+            max_attempts: Some(1),
+            on_failure: Some(FailurePolicy::Reset),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Blocked);
+        assert!(
+            a.attempts[0]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("did not commit")
+        );
+        assert!(!repo.dir.join("draft.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn agent_can_hand_a_story_to_a_human() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[]), story("B", 2, &[])]),
+            serde_json::json!({"responses": [
+                {"story": "A", "set_status": "needs_human", "text": "Which database?"},
+                commit_and_pass("B"),
+            ]}),
+        );
+
+        run_command(repo.options()).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::NeedsHuman);
+        assert_eq!(a.attempts[0].outcome, AttemptOutcome::NeedsHuman);
+        assert_eq!(repo.story("B").status, StoryStatus::Passed);
+    }
+
+    #[tokio::test]
+    async fn scope_revert_undoes_out_of_scope_changes() {
+        let mut scoped = story("A", 1, &[]);
+        scoped["scope"] = serde_json::json!(["src/**"]);
+        let repo = TestRepo::new(
+            prd(vec![scoped]),
+            serde_json::json!({"responses": [{
+                "commands": ["mkdir -p src && echo ok > src/a.rs && echo no > other.txt && git add -A src other.txt && git commit -qm work"],
+                "mark_passed": true,
+            }]}),
+        );
+        let options = RunOptions {
+            scope_policy: Some(ScopePolicy::Revert),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        assert_eq!(repo.story("A").status, StoryStatus::Passed);
+        assert!(repo.dir.join("src/a.rs").exists());
+        assert!(!repo.dir.join("other.txt").exists());
+        assert!(
+            repo.progress()
+                .contains("reverted changes outside scope: other.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn reverting_the_only_commit_fails_the_attempt() {
+        let mut scoped = story("A", 1, &[]);
+        scoped["scope"] = serde_json::json!(["src/**"]);
+        let repo = TestRepo::new(
+            prd(vec![scoped]),
+            serde_json::json!({"responses": [{
+                "commands": ["echo no > other.txt && git add other.txt && git commit -qm work"],
+                "mark_passed": true,
+            }]}),
+        );
+        let options = RunOptions {
+            scope_policy: Some(ScopePolicy::Revert),
+            max_attempts: Some(1),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Blocked);
+        assert_eq!(a.attempts[0].outcome, AttemptOutcome::Failed);
+        assert!(
+            a.attempts[0]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("no changes")
+        );
+        assert_eq!(a.attempts[0].commit, None);
+        assert!(!repo.dir.join("other.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn dry_run_changes_nothing() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+        let prompts = repo.dir.join("prompts");
+        let options = RunOptions {
+            dry_run: true,
+            prompts_dir: Some(prompts.clone()),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        assert_eq!(repo.story("A").status, StoryStatus::Pending);
+        assert_eq!(repo.git(&["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+        assert_eq!(fs::read_dir(&prompts).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn failing_verification_keeps_the_story_from_passing() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+        repo.configure(serde_json::json!({"verify": [
+            {"name": "lint", "command": "true"},
+            {"name": "test", "command": "echo 'assertion failed: left == right'; exit 3"},
+            {"name": "never", "command": "touch never-run"},
+        ]}));
+        let options = RunOptions {
+            max_attempts: Some(1),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Blocked);
+        assert_eq!(a.attempts[0].outcome, AttemptOutcome::Failed);
+        assert!(
+            a.attempts[0]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("Verification failed")
+        );
+        let progress = repo.progress();
+        assert!(progress.contains("Step `test`"));
+        assert!(progress.contains("exited with 3"));
+        assert!(progress.contains("assertion failed: left == right"));
+        assert!(!repo.dir.join("never-run").exists());
+    }
+
+    #[tokio::test]
+    async fn story_verify_replaces_the_project_steps() {
+        let mut verified = story("A", 1, &[]);
+        verified["verify"] = serde_json::json!([{"name": "file", "command": "test -f A.txt"}]);
+        let repo = TestRepo::new(
+            prd(vec![verified]),
+            serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+        repo.configure(serde_json::json!({"verify": [{"name": "project", "command": "exit 1"}]}));
+
+        run_command(repo.options()).await.unwrap();
+
+        assert_eq!(repo.story("A").status, StoryStatus::Passed);
+        assert!(!repo.progress().contains("Step `project`"));
+    }
+
+    fn branches(repo: &TestRepo) -> Vec<String> {
+        repo.git(&["branch", "--format=%(refname:short)"])
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn parallel_stories_merge_back() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[]), story("B", 2, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A"), commit_and_pass("B")]}),
+        );
+        let options = RunOptions {
+            jobs: 2,
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        for id in ["A", "B"] {
+            assert_eq!(repo.story(id).status, StoryStatus::Passed);
+            assert!(repo.dir.join(format!("{id}.txt")).exists());
+        }
+        assert_eq!(
+            repo.git(&["log", "--merges", "--format=%s"])
+                .lines()
+                .count(),
+            2
+        );
+        assert_eq!(branches(&repo), ["feature/test", "main"]);
+    }
+
+    #[tokio::test]
+    async fn merge_conflict_fails_the_attempt_and_keeps_its_branch() {
+        let write = |story: &str, delay_ms: u64| {
+            serde_json::json!({
+                "story": story,
+                "commands": [format!("echo {story} > shared.txt && git add shared.txt && git commit -qm 'Write {story}'")],
+                "mark_passed": true,
+                "delay_ms": delay_ms,
+            })
+        };
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[]), story("B", 2, &[])]),
+            serde_json::json!({"responses": [write("A", 0), write("B", 300)]}),
+        );
+        let options = RunOptions {
+            jobs: 2,
+            max_attempts: Some(1),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        assert_eq!(repo.story("A").status, StoryStatus::Passed);
+        let b = repo.story("B");
+        assert_eq!(b.status, StoryStatus::Blocked);
+        let error = b.attempts[0].error.as_deref().unwrap();
+        assert!(error.contains("Merge conflict"), "{error}");
+        assert_eq!(
+            fs::read_to_string(repo.dir.join("shared.txt")).unwrap(),
+            "A\n"
+        );
+        assert!(branches(&repo).contains(&"feature/test-b-attempt-1".to_string()));
+        assert_eq!(
+            repo.git(&["status", "--porcelain", "--untracked-files=no"]),
+            "M prd.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn worktree_commits_to_ramph_files_are_undone() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [{
+                "commands": [
+                    "sed -i.bak 's/Do the thing/Hacked/' prd.json && rm prd.json.bak",
+                    "echo 'learned: use tabs' >> progress.txt",
+                    "echo A > A.txt && git add -A && git commit -qm 'Add A'",
+                ],
+                "mark_passed": true,
+            }]}),
+        );
+        let options = RunOptions {
+            jobs: 2,
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        assert_eq!(repo.story("A").status, StoryStatus::Passed);
+        assert!(repo.dir.join("A.txt").exists());
+        assert!(
+            !repo
+                .git(&["show", "feature/test:prd.json"])
+                .contains("Hacked")
+        );
+        assert_eq!(repo.git(&["ls-files", "progress.txt"]), "");
+        assert!(repo.progress().contains("learned: use tabs"));
+    }
+
+    #[tokio::test]
+    async fn taken_attempt_branch_fails_only_that_attempt() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[]), story("B", 2, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A"), commit_and_pass("B")]}),
+        );
+        repo.git(&["branch", "feature/test-a-attempt-1"]);
+        let options = RunOptions {
+            jobs: 2,
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Passed);
+        let outcomes: Vec<(u32, AttemptOutcome)> = a
+            .attempts
+            .iter()
+            .map(|at| (at.number, at.outcome))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [(1, AttemptOutcome::Failed), (2, AttemptOutcome::Passed)]
+        );
+        assert!(
+            a.attempts[0]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("feature/test-a-attempt-1 already exists")
+        );
+        assert_eq!(repo.story("B").status, StoryStatus::Passed);
+    }
+
+    /// Runs ramph with `options`, interrupting it after `after`.
+    async fn run_interrupted(options: RunOptions, after: Duration) -> Result<()> {
+        let interrupt = Interrupt::default();
+        let trigger = interrupt.clone();
+        let (result, ()) = tokio::join!(run(options, Some(interrupt)), async move {
+            tokio::time::sleep(after).await;
+            trigger.trigger();
+        });
+        result
+    }
+
+    #[tokio::test]
+    async fn interrupted_attempt_resumes_its_session_without_using_budget() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [
+                {"story": "A", "session": "thread-1", "delay_ms": 10000},
+            ]}),
+        );
+        let options = || RunOptions {
+            max_attempts: Some(1),
+            ..repo.options()
+        };
+
+        run_interrupted(options(), Duration::from_millis(300))
+            .await
+            .unwrap();
+
+        let state = state::load_state(&repo.dir).unwrap().unwrap();
+        assert_eq!(state.status, RunStatus::Interrupted);
+        assert_eq!(state.current_stories, ["A"]);
+        assert_eq!(state.last_session("A"), Some("thread-1"));
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::InProgress);
+        assert_eq!(a.attempt_count, 0);
+        assert_eq!(a.attempts[0].outcome, AttemptOutcome::Interrupted);
+        assert!(repo.progress().contains("Interrupted: A (attempt 1/1)"));
+
+        // Resuming keeps the tree as the interrupted attempt left it, edits included
+        repo.write(
+            "fake.json",
+            &serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+        run_command(RunOptions {
+            resume: true,
+            ..options()
+        })
+        .await
+        .unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Passed);
+        assert_eq!(a.attempt_count, 1);
+        assert_eq!(a.attempts[1].number, 2);
+        assert_eq!(a.attempts[1].session_id.as_deref(), Some("thread-1"));
+        let state = state::load_state(&repo.dir).unwrap().unwrap();
+        assert_eq!(state.status, RunStatus::Finished);
+        assert!(state.current_stories.is_empty());
+        assert!(repo.progress().contains("resumed on feature/test"));
+    }
+
+    #[tokio::test]
+    async fn resume_refuses_finished_runs_and_other_branches() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [commit_and_pass("A")]}),
+        );
+        let resume = || RunOptions {
+            resume: true,
+            ..repo.options()
+        };
+
+        let error = run_command(resume()).await.unwrap_err();
+        assert!(error.to_string().contains("No previous run to resume"));
+
+        run_command(repo.options()).await.unwrap();
+        let mut state = state::load_state(&repo.dir).unwrap().unwrap();
+        assert_eq!(state.status, RunStatus::Finished);
+        assert_eq!(state.branch, "feature/test");
+        assert_eq!(state.iteration, 1);
+        let error = run_command(resume()).await.unwrap_err();
+        assert!(error.to_string().contains("already finished"), "{error}");
+
+        state.status = RunStatus::Interrupted;
+        state::save_state(&repo.dir, &mut state).unwrap();
+        let mut moved = repo.prd();
+        moved.branch_name = "feature/other".to_string();
+        save_prd(&repo.dir.join("prd.json"), &moved).unwrap();
+        let error = run_command(resume()).await.unwrap_err();
+        assert!(
+            error.to_string().contains("was on branch feature/test"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_attempt_times_out() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [{"story": "A", "delay_ms": 10000}]}),
+        );
+        let options = RunOptions {
+            max_attempts: Some(1),
+            iteration_timeout: Some(Duration::from_millis(200)),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        let a = repo.story("A");
+        assert_eq!(a.status, StoryStatus::Blocked);
+        assert_eq!(a.attempt_count, 1);
+        assert_eq!(a.attempts[0].outcome, AttemptOutcome::TimedOut);
+        assert!(repo.progress().contains("Timed out: A (attempt 1/1)"));
+    }
+
+    #[tokio::test]
+    async fn deadline_stops_new_stories() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[]), story("B", 2, &[])]),
+            serde_json::json!({"responses": [
+                {
+                    "story": "A",
+                    "commands": ["echo A > A.txt && git add A.txt && git commit -qm 'Add A'"],
+                    "mark_passed": true,
+                    "delay_ms": 500,
+                },
+                commit_and_pass("B"),
+            ]}),
+        );
+        let options = RunOptions {
+            deadline: Some(Local::now() + chrono::Duration::milliseconds(200)),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        assert_eq!(repo.story("A").status, StoryStatus::Passed);
+        let b = repo.story("B");
+        assert_eq!(b.status, StoryStatus::Pending);
+        assert!(b.attempts.is_empty());
+        assert!(repo.progress().contains("Deadline reached; run stopped"));
+    }
+
+    #[tokio::test]
+    async fn stash_skips_files_the_agent_deleted() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [{"commands": ["rm scratch.txt && echo draft > draft.txt"]}]}),
+        );
+        fs::write(repo.dir.join("scratch.txt"), "notes").unwrap();
+        let options = RunOptions {
+            max_attempts: Some(1),
+            on_failure: Some(FailurePolicy::Stash),
+            ..repo.options()
+        };
+
+        run_command(options).await.unwrap();
+
+        assert!(
+            repo.progress()
+                .contains("Rollback: stashed changes as \"ramph: A attempt 1/1 failed\"")
+        );
+        assert_eq!(repo.git(&["stash", "list"]).lines().count(), 1);
+        assert!(!repo.dir.join("draft.txt").exists());
+    }
+
+    /// The only conversation saved under `.ramph/plans/`.
+    fn saved_conversation(repo: &TestRepo) -> Conversation {
+        let plans = repo.dir.join(".ramph/plans");
+        let mut files: Vec<PathBuf> = fs::read_dir(plans)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        Conversation::load(&files.pop().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn plan_converses_until_done_and_saves_the_prd() {
+        let planned = prd(vec![story("STORY-001", 1, &[])]);
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [
+                {"prompt_contains": "structured data extraction", "text": planned.to_string()},
+                {"prompt_contains": "Initial Project Description", "text": "Which database?"},
+                {"prompt_contains": "Postgres", "text": "Noted, one story then."},
+            ]}),
+        );
+
+        let mut input = "/help\n/show\n/restart\n\nPostgres\n/bogus\n/done\ny\n".as_bytes();
+        plan(repo.plan_options(), &mut input).await.unwrap();
+
+        let saved = load_prd(&repo.dir.join("planned.json")).unwrap();
+        assert_eq!(saved.stories[0].id, "STORY-001");
+        // The restart dropped the first exchange
+        let conversation = saved_conversation(&repo);
+        let turns: Vec<(Speaker, &str)> = conversation
+            .turns
+            .iter()
+            .map(|turn| (turn.speaker, turn.text.as_str()))
+            .collect();
+        assert_eq!(
+            turns,
+            [
+                (Speaker::Agent, "Which database?"),
+                (Speaker::User, "Postgres"),
+                (Speaker::Agent, "Noted, one story then."),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn plan_extracts_from_a_saved_conversation_and_repairs_invalid_json() {
+        let planned = prd(vec![story("STORY-001", 1, &[])]);
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [
+                // Only a repair prompt carrying the parse error gets the valid PRD
+                {"prompt_contains": "Failed to extract JSON", "text": planned.to_string()},
+                {"prompt_contains": "Use SQLite", "text": "Sorry, no JSON today"},
+            ]}),
+        );
+        let mut conversation = Conversation::default();
+        conversation.push(Speaker::User, "A todo app. Use SQLite.");
+        conversation.push(Speaker::Agent, "Got it.");
+        conversation.save(&repo.dir.join("chat.json")).unwrap();
+        let options = PlanOptions {
+            extract_from: Some(PathBuf::from("chat.json")),
+            description: None,
+            ..repo.plan_options()
+        };
+
+        plan(options, &mut "y\n".as_bytes()).await.unwrap();
+
+        let saved = load_prd(&repo.dir.join("planned.json")).unwrap();
+        assert_eq!(saved.stories[0].id, "STORY-001");
+    }
+
+    #[tokio::test]
+    async fn plan_gives_up_after_the_repair_attempts() {
+        let repo = TestRepo::new(
+            prd(vec![story("A", 1, &[])]),
+            serde_json::json!({"responses": [{"text": "{\"branchName\": \"\", \"stories\": []}"}]}),
+        );
+        let mut conversation = Conversation::default();
+        conversation.push(Speaker::User, "A todo app");
+        conversation.save(&repo.dir.join("chat.json")).unwrap();
+        let options = PlanOptions {
+            extract_from: Some(PathBuf::from("chat.json")),
+            description: None,
+            repair_attempts: 1,
+            ..repo.plan_options()
+        };
+
+        let error = plan(options, &mut "y\n".as_bytes()).await.unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("after 1 repair attempt"), "{message}");
+        assert!(message.contains("--extract-from"), "{message}");
+        assert!(!repo.dir.join("planned.json").exists());
+    }
+
+    fn story_command(repo: &TestRepo, action: StoryAction) -> Result<()> {
+        run_story_command(repo.dir.clone(), PathBuf::from("prd.json"), action)
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn story_add_takes_the_next_id_and_priority() {
+        let repo = TestRepo::new(
+            prd(vec![story("STORY-001", 1, &[]), story("STORY-002", 5, &[])]),
+            serde_json::json!({"responses": []}),
+        );
+        let edit = || StoryEdit {
+            title: Some("Export".to_string()),
+            description: Some("Export the data".to_string()),
+            acceptance_criteria: strings(&["A CSV file is written"]),
+            ..Default::default()
+        };
+
+        story_command(
+            &repo,
+            StoryAction::Add {
+                id: None,
+                edit: edit(),
+            },
+        )
+        .unwrap();
+        let added = repo.story("STORY-003");
+        assert_eq!(added.title, "Export");
+        assert_eq!(added.priority, 6);
+        assert_eq!(added.status, StoryStatus::Pending);
+
+        let duplicate = StoryAction::Add {
+            id: Some("STORY-001".to_string()),
+            edit: edit(),
+        };
+        assert!(story_command(&repo, duplicate).is_err());
+        assert_eq!(repo.prd().stories.len(), 3);
+    }
+
+    #[test]
+    fn story_edit_replaces_and_clears_lists() {
+        let mut tagged = story("STORY-001", 1, &[]);
+        tagged["tags"] = serde_json::json!(["backend"]);
+        let repo = TestRepo::new(
+            prd(vec![tagged, story("STORY-002", 2, &["STORY-001"])]),
+            serde_json::json!({"responses": []}),
+        );
+
+        let edit = StoryEdit {
+            acceptance_criteria: strings(&["One", "Two"]),
+            tags: strings(&["api", "infra"]),
+            ..Default::default()
+        };
+        story_command(
+            &repo,
+            StoryAction::Edit {
+                id: "STORY-001".to_string(),
+                edit,
+            },
+        )
+        .unwrap();
+        let edited = repo.story("STORY-001");
+        assert_eq!(edited.acceptance_criteria, ["One", "Two"]);
+        assert_eq!(edited.tags, ["api", "infra"]);
+        assert_eq!(edited.title, "Story STORY-001");
+
+        let clear = |id: &str| StoryAction::Edit {
+            id: id.to_string(),
+            edit: StoryEdit {
+                clear_tags: true,
+                clear_depends_on: true,
+                ..Default::default()
+            },
+        };
+        story_command(&repo, clear("STORY-001")).unwrap();
+        story_command(&repo, clear("STORY-002")).unwrap();
+        assert!(repo.story("STORY-001").tags.is_empty());
+        assert!(repo.story("STORY-002").depends_on.is_empty());
+
+        let nothing = StoryAction::Edit {
+            id: "STORY-001".to_string(),
+            edit: StoryEdit::default(),
+        };
+        assert!(story_command(&repo, nothing).is_err());
+    }
+
+    #[test]
+    fn story_remove_needs_force_to_drop_dependencies() {
+        let repo = TestRepo::new(
+            prd(vec![
+                story("STORY-001", 1, &[]),
+                story("STORY-002", 2, &["STORY-001"]),
+            ]),
+            serde_json::json!({"responses": []}),
+        );
+        let remove = |force| StoryAction::Remove {
+            ids: strings(&["STORY-001"]),
+            force,
+        };
+
+        let error = story_command(&repo, remove(false)).unwrap_err();
+        assert!(error.to_string().contains("STORY-002"), "{error:#}");
+        assert_eq!(repo.prd().stories.len(), 2);
+
+        story_command(&repo, remove(true)).unwrap();
+        let prd = repo.prd();
+        assert_eq!(prd.stories.len(), 1);
+        assert_eq!(prd.stories[0].id, "STORY-002");
+        assert!(prd.stories[0].depends_on.is_empty());
+    }
+
+    #[test]
+    fn status_report_lists_totals_next_story_and_stuck_stories() {
+        let with_status = |mut story: serde_json::Value, status: &str| {
+            story["status"] = serde_json::json!(status);
+            story
+        };
+        let mut passed = with_status(story("A", 1, &[]), "passed");
+        passed["attempts"] = serde_json::json!([{
+            "number": 1,
+            "started_at": "2026-01-01T10:00:00+00:00",
+            "finished_at": "2026-01-01T10:00:01+00:00",
+            "outcome": "passed",
+            "duration_ms": 1000,
+        }]);
+        let prd: Prd = serde_json::from_value(prd(vec![
+            passed,
+            with_status(story("B", 2, &[]), "blocked"),
+            story("C", 3, &["B"]),
+            with_status(story("D", 4, &[]), "needs_human"),
+            story("E", 5, &[]),
+            story("F", 6, &["E"]),
+        ]))
+        .unwrap();
+        let mut last_run = RunState::new("feature/test", "abc123");
+        last_run.status = RunStatus::Interrupted;
+
+        let report = serde_json::to_value(status_report(&prd, Some(last_run))).unwrap();
+
+        assert_eq!(report["validation_error"], serde_json::Value::Null);
+        let totals = &report["totals"];
+        assert_eq!(totals["stories"], 6);
+        assert_eq!(totals["attempts"], 1);
+        assert_eq!(totals["duration_ms"], 1000);
+        assert_eq!(
+            totals["by_status"],
+            serde_json::json!({
+                "pending": 3, "in_progress": 0, "passed": 1, "failed": 0,
+                "blocked": 1, "skipped": 0, "needs_human": 1,
+            })
+        );
+        assert_eq!(report["next_story"], "E");
+        assert_eq!(report["blocked"], serde_json::json!(["B"]));
+        assert_eq!(report["needs_human"], serde_json::json!(["D"]));
+        assert_eq!(report["waiting"], serde_json::json!(["C"]));
+        assert_eq!(report["last_run"]["status"], "interrupted");
+        assert_eq!(report["last_run"]["start_commit"], "abc123");
+        assert_eq!(report["stories"][0]["last_attempt"]["outcome"], "passed");
+        assert_eq!(report["stories"][2]["depends_on"], serde_json::json!(["B"]));
+        assert!(report["stories"][4].get("depends_on").is_none());
+    }
 }
@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+
+/// An isolated checkout of the feature branch in which a single story attempt runs.
+pub struct StoryWorktree {
+    pub story_id: String,
+    pub branch: String,
+    root: PathBuf,
+    cwd: PathBuf,
+    fork_commit: String,
+    prd_rel: PathBuf,
+    progress_rel: PathBuf,
+    progress_snapshot: String,
+}
+
+pub struct WorktreeOutcome {
+    /// Text the agent appended to the worktree's progress file
+    pub learnings: Option<String>,
+    /// `None` if the attempt produced no commits
+    pub merge: Option<git::MergeResult>,
+}
+
+impl StoryWorktree {
+    /// Creates a worktree for `story_id` on a throwaway branch forked from the current HEAD,
+    /// seeded with copies of the PRD and progress files so the agent sees the usual layout.
+    ///
+    /// Each attempt gets its own branch. An existing branch of that name is never reused, as
+    /// it may hold the work of an attempt kept after a merge conflict.
+    pub fn create(
+        repo_cwd: &Path,
+        worktrees_dir: &Path,
+        feature_branch: &str,
+        story_id: &str,
+        attempt: u32,
+        prd_rel: &Path,
+        progress_rel: &Path,
+    ) -> Result<Self> {
+        let fork_commit = git::head_commit(repo_cwd)?;
+        let branch = worktree_branch_name(feature_branch, story_id, attempt);
+        anyhow::ensure!(
+            !git::branch_exists(repo_cwd, &branch)?,
+            "Branch {branch} already exists (kept after a merge conflict?); merge or delete it before retrying {story_id}"
+        );
+        let root = worktrees_dir.join(story_id);
+
+        if root.exists() {
+            let _ = git::worktree_remove(repo_cwd, &root);
+            let _ = fs::remove_dir_all(&root);
+        }
+
+        git::worktree_add(repo_cwd, &root, &branch, &fork_commit)
+            .with_context(|| format!("Failed to create worktree for {story_id}"))?;
+
+        let cwd = root.join(git::show_prefix(repo_cwd)?);
+        copy_into(&repo_cwd.join(prd_rel), &cwd.join(prd_rel))?;
+
+        let progress_src = repo_cwd.join(progress_rel);
+        let progress_snapshot = if progress_src.exists() {
+            copy_into(&progress_src, &cwd.join(progress_rel))?;
+            fs::read_to_string(&progress_src).unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(Self {
+            story_id: story_id.to_string(),
+            branch,
+            root,
+            cwd,
+            fork_commit,
+            prd_rel: prd_rel.to_path_buf(),
+            progress_rel: progress_rel.to_path_buf(),
+            progress_snapshot,
+        })
+    }
+
+    /// Project directory inside the worktree (where the agent and verification run).
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    pub fn prd_path(&self) -> PathBuf {
+        self.cwd.join(&self.prd_rel)
+    }
+
+    pub fn progress_path(&self) -> PathBuf {
+        self.cwd.join(&self.progress_rel)
+    }
+
+    /// Collects the agent's learnings, undoes any commits it made to the PRD or progress
+    /// file (ramph updates those itself), then merges the story branch into the current
+    /// branch of `repo_cwd` if `merge` is set.
+    ///
+    /// The worktree is always removed; the story branch is kept only when the merge conflicts.
+    pub fn finish(self, repo_cwd: &Path, merge: bool) -> Result<WorktreeOutcome> {
+        let learnings = fs::read_to_string(self.cwd.join(&self.progress_rel))
+            .ok()
+            .and_then(|current| {
+                current
+                    .strip_prefix(&self.progress_snapshot)
+                    .map(|added| added.trim().to_string())
+            })
+            .filter(|added| !added.is_empty());
+
+        let managed = [
+            self.prd_rel.to_string_lossy().to_string(),
+            self.progress_rel.to_string_lossy().to_string(),
+        ];
+        let managed: Vec<&str> = managed.iter().map(String::as_str).collect();
+        let touched = git::changed_paths(&self.cwd, &self.fork_commit, &managed)?;
+        if !touched.is_empty() {
+            git::restore_paths(
+                &self.cwd,
+                &self.fork_commit,
+                &touched,
+                &format!("ramph: restore PRD and progress files ({})", self.story_id),
+                false,
+            )?;
+        }
+
+        let merge = if merge && git::commit_count_since(&self.cwd, &self.fork_commit)? > 0 {
+            Some(git::merge_branch(
+                repo_cwd,
+                &self.branch,
+                &format!("Merge {} ({})", self.story_id, self.branch),
+            )?)
+        } else {
+            None
+        };
+
+        git::worktree_remove(repo_cwd, &self.root)?;
+        if !matches!(merge, Some(git::MergeResult::Conflict { .. })) {
+            git::delete_branch(repo_cwd, &self.branch)?;
+        }
+
+        Ok(WorktreeOutcome { learnings, merge })
+    }
+}
+
+/// Name of the throwaway branch a story attempt's worktree is checked out on.
+fn worktree_branch_name(feature_branch: &str, story_id: &str, attempt: u32) -> String {
+    format!(
+        "{}-{}-attempt-{}",
+        feature_branch,
+        story_id.to_lowercase(),
+        attempt
+    )
+}
+
+fn copy_into(src: &Path, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(src, dest)
+        .with_context(|| format!("Failed to copy {} into worktree", src.display()))?;
+    Ok(())
+}